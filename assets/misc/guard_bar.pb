@@ -0,0 +1,28 @@
+ProgressBarMaterial(
+    size: (150.0, 10.0),
+    color_light: Rgba(
+        red: 1.0,
+        green: 0.85,
+        blue: 0.2,
+        alpha: 1.0,
+    ),
+    color_dark: Rgba(
+        red: 0.9,
+        green: 0.7,
+        blue: 0.0,
+        alpha: 1.0,
+    ),
+    color_bg: Rgba(
+        red: 0.1,
+        green: 0.1,
+        blue: 0.1,
+        alpha: 1.0,
+    ),
+    color_fg: Rgba(
+        red: 0.2,
+        green: 0.2,
+        blue: 0.2,
+        alpha: 1.0,
+    ),
+    sections: [0.5],
+)
\ No newline at end of file
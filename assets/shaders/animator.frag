@@ -6,6 +6,10 @@ layout(location = 0) in vec2 v_Uv;
 layout(set = 2, binding = 0) uniform texture2D Animator_texture;
 layout(set = 2, binding = 1) uniform sampler Animator_texture_sampler;
 
+layout(set = 2, binding = 6) uniform Animator_flash {
+    float flash;
+};
+
 void main() {
     vec4 color = texture(sampler2D(Animator_texture, Animator_texture_sampler), v_Uv);
 
@@ -13,5 +17,5 @@ void main() {
         discard;
     }
 
-    o_Target = color;
+    o_Target = vec4(mix(color.rgb, vec3(1.0), flash), color.a);
 }
\ No newline at end of file
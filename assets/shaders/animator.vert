@@ -27,9 +27,17 @@ layout(set = 2, binding = 4) uniform Animator_rows {
     uint rows;
 };
 
+layout(set = 2, binding = 5) uniform Animator_flip_x {
+    uint flip_x;
+};
+
 void main() {
     vec2 uv = Vertex_Uv;
 
+    if (flip_x == 1) {
+        uv.x = 1.0 - uv.x;
+    }
+
     vec2 size = vec2(textureSize(sampler2D(Animator_texture, Animator_texture_sampler), 0));
     vec2 dimensions = size / vec2(float(columns), float(rows));
 
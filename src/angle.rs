@@ -1,6 +1,8 @@
+use bevy::prelude::*;
 use serde::{Deserialize, Serialize};
 
-#[derive(Serialize, Deserialize)]
+/// The eight directions a character can face, clockwise from up.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub enum Angle {
     Up,
     UpRight,
@@ -11,3 +13,40 @@ pub enum Angle {
     Left,
     UpLeft,
 }
+
+impl Angle {
+    pub const ALL: [Angle; 8] = [
+        Angle::Up,
+        Angle::UpRight,
+        Angle::Right,
+        Angle::DownRight,
+        Angle::Down,
+        Angle::DownLeft,
+        Angle::Left,
+        Angle::UpLeft,
+    ];
+
+    /// The angle closest to `direction`, a zero direction faces the camera.
+    pub fn from_direction(direction: Vec2) -> Self {
+        if direction.length_squared() == 0.0 {
+            return Angle::Down;
+        }
+
+        // turns clockwise from up
+        let turns = direction.x.atan2(direction.y) / std::f32::consts::TAU;
+
+        Self::ALL[(turns * 8.0).round().rem_euclid(8.0) as usize % 8]
+    }
+
+    /// The angle mirrored along the vertical axis, up and down mirror to themselves.
+    pub fn mirrored(self) -> Self {
+        Self::ALL[(8 - self as usize) % 8]
+    }
+
+    /// The number of eighth turns between two angles.
+    pub fn distance(self, other: Angle) -> usize {
+        let steps = (self as usize + 8 - other as usize) % 8;
+
+        steps.min(8 - steps)
+    }
+}
@@ -1,4 +1,7 @@
+use crate::angle::*;
+use crate::animation_event::*;
 use crate::networking::*;
+use crate::server::TICK_RATE;
 use bevy::{
     prelude::*,
     reflect::TypeUuid,
@@ -9,11 +12,19 @@ use bevy::{
         shader::{ShaderDefs, ShaderStages},
     },
 };
+use serde::de::{
+    self,
+    value::{MapAccessDeserializer, StrDeserializer},
+    Deserializer, IntoDeserializer, MapAccess, Visitor,
+};
+use serde::Serializer;
+use std::{collections::HashMap, fmt};
 
 pub const ANIMATOR_PIPELINE_HANDLE: HandleUntyped =
     HandleUntyped::weak_from_u64(PipelineDescriptor::TYPE_UUID, 1684523432231);
 
 #[derive(Serialize, Deserialize, TypeUuid)]
+#[serde(deny_unknown_fields)]
 #[uuid = "cbf6ae0d-a8d3-4ed0-9a52-0575b587051a"]
 pub struct Animation {
     pub image: String,
@@ -22,6 +33,146 @@ pub struct Animation {
     pub length: u32,
     pub fps: f32,
     pub looping: bool,
+    /// Cosmetic events fired on the client when the animation reaches a frame, by frame.
+    #[serde(default, deserialize_with = "crate::helper::unique_map")]
+    pub events: HashMap<u32, Vec<AnimationEvent>>,
+}
+
+/// An animation, or one animation per [`Angle`].
+///
+/// Angles without an animation use the mirrored angle flipped, or else the nearest angle.
+/// In asset files this is either a path or a map of paths with at least one angle,
+/// the angles have to be quoted, `{ "Right": "...", "Up": "..." }`.
+#[derive(Clone, Debug, PartialEq)]
+pub enum DirectionalAnimation {
+    Single(String),
+    Directional(HashMap<Angle, String>),
+}
+
+impl DirectionalAnimation {
+    /// The animation played when facing `angle`, and whether it's mirrored.
+    pub fn get(&self, angle: Angle) -> (&str, bool) {
+        let paths = match self {
+            DirectionalAnimation::Single(path) => return (path, false),
+            DirectionalAnimation::Directional(paths) => paths,
+        };
+
+        if let Some(path) = paths.get(&angle) {
+            return (path, false);
+        }
+
+        if let Some(path) = paths.get(&angle.mirrored()) {
+            return (path, true);
+        }
+
+        let nearest = Angle::ALL
+            .iter()
+            .filter(|other| paths.contains_key(*other))
+            .min_by_key(|other| other.distance(angle))
+            .expect("directional animations need at least one angle");
+
+        (&paths[nearest], false)
+    }
+
+    pub fn paths(&self) -> Vec<&String> {
+        match self {
+            DirectionalAnimation::Single(path) => vec![path],
+            DirectionalAnimation::Directional(paths) => paths.values().collect(),
+        }
+    }
+}
+
+impl From<String> for DirectionalAnimation {
+    fn from(path: String) -> Self {
+        DirectionalAnimation::Single(path)
+    }
+}
+
+impl From<&str> for DirectionalAnimation {
+    fn from(path: &str) -> Self {
+        DirectionalAnimation::Single(String::from(path))
+    }
+}
+
+/// Reads the asset file form of [`DirectionalAnimation`], a path or a map of paths.
+struct AssetDirectionalAnimationVisitor;
+
+impl<'de> Visitor<'de> for AssetDirectionalAnimationVisitor {
+    type Value = DirectionalAnimation;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("an animation path or a map of angles to animation paths")
+    }
+
+    fn visit_str<E: de::Error>(self, path: &str) -> Result<Self::Value, E> {
+        Ok(DirectionalAnimation::Single(String::from(path)))
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, map: A) -> Result<Self::Value, A::Error> {
+        // ron only reads enum variants without quotes, so the angles are read as strings
+        let paths: HashMap<String, String> =
+            crate::helper::unique_map(MapAccessDeserializer::new(map))?;
+
+        paths
+            .into_iter()
+            .map(|(angle, path)| {
+                let angle: StrDeserializer<A::Error> = angle.as_str().into_deserializer();
+                Ok::<_, A::Error>((Angle::deserialize(angle)?, path))
+            })
+            .collect::<Result<_, _>>()
+            .map(DirectionalAnimation::Directional)
+    }
+}
+
+/// The network form of [`DirectionalAnimation`], bincode can't read the asset form.
+#[derive(Deserialize)]
+#[serde(rename = "DirectionalAnimation")]
+enum TaggedDirectionalAnimation {
+    Single(String),
+    Directional(#[serde(deserialize_with = "crate::helper::unique_map")] HashMap<Angle, String>),
+}
+
+impl Serialize for DirectionalAnimation {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let name = "DirectionalAnimation";
+
+        match (self, serializer.is_human_readable()) {
+            (DirectionalAnimation::Single(path), true) => path.serialize(serializer),
+            (DirectionalAnimation::Directional(paths), true) => paths.serialize(serializer),
+            (DirectionalAnimation::Single(path), false) => {
+                serializer.serialize_newtype_variant(name, 0, "Single", path)
+            }
+            (DirectionalAnimation::Directional(paths), false) => {
+                serializer.serialize_newtype_variant(name, 1, "Directional", paths)
+            }
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for DirectionalAnimation {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let animation = if deserializer.is_human_readable() {
+            deserializer.deserialize_any(AssetDirectionalAnimationVisitor)?
+        } else {
+            match TaggedDirectionalAnimation::deserialize(deserializer)? {
+                TaggedDirectionalAnimation::Single(path) => DirectionalAnimation::Single(path),
+                TaggedDirectionalAnimation::Directional(paths) => {
+                    DirectionalAnimation::Directional(paths)
+                }
+            }
+        };
+
+        // `get` falls back to the nearest angle, so there has to be one
+        if let DirectionalAnimation::Directional(paths) = &animation {
+            if paths.is_empty() {
+                return Err(de::Error::custom(
+                    "directional animations need at least one angle",
+                ));
+            }
+        }
+
+        Ok(animation)
+    }
 }
 
 #[derive(RenderResources, ShaderDefs)]
@@ -31,14 +182,29 @@ pub struct Animator {
     pub frame: u32,
     pub columns: u32,
     pub rows: u32,
+    /// 1 when the current angle plays the animation of the mirrored angle.
+    pub flip_x: u32,
+    /// How white the sprite is flashed, from 0 to 1.
+    pub flash: f32,
+    /// The flash lost per second.
+    #[render_resources(ignore)]
+    pub flash_fade: f32,
     #[render_resources(ignore)]
     pub play_time: f32,
     #[render_resources(ignore)]
-    pub animation: Option<String>,
+    pub animation: Option<DirectionalAnimation>,
+    #[render_resources(ignore)]
+    pub angle: Angle,
     #[render_resources(ignore)]
     pub just_ended: bool,
     #[render_resources(ignore)]
     pub just_advanced: bool,
+    /// The last frame passed, `None` until the animation has advanced.
+    #[render_resources(ignore)]
+    previous_frame: Option<u32>,
+    /// The events of every frame passed in the last update, in order.
+    #[render_resources(ignore)]
+    pub events: Vec<AnimationEvent>,
 }
 
 impl Animator {
@@ -48,10 +214,16 @@ impl Animator {
             frame: 0,
             columns: 1,
             rows: 1,
+            flip_x: 0,
+            flash: 0.0,
+            flash_fade: 0.0,
             play_time: 0.0,
             animation: None,
+            angle: Angle::Down,
             just_ended: false,
             just_advanced: false,
+            previous_frame: None,
+            events: Vec::new(),
         }
     }
 
@@ -60,30 +232,49 @@ impl Animator {
     }
 
     pub fn advance(&mut self, animation: &Animation, delta_time: f32) {
-        if self.play_time == 0.0 {
-            self.just_advanced = true;
-        }
-
         self.columns = animation.columns;
         self.rows = animation.rows;
 
         self.play_time += delta_time;
         let frame_time = 1.0 / animation.fps;
+        let duration = frame_time * animation.length as f32;
 
-        if self.play_time > frame_time * animation.length as f32 {
-            if animation.looping {
-                self.play_time = 0.0;
-            } else {
-                self.stop();
-                self.just_ended = true;
+        let over = self.play_time > duration;
+        let looped = over && animation.looping;
+        let ended = over && !animation.looping;
+
+        if looped {
+            self.play_time %= duration;
+        }
+
+        // an animation that ended passed every frame up to its last one
+        let last_frame = animation.length.saturating_sub(1);
+        let frame = if ended {
+            last_frame
+        } else {
+            ((self.play_time / frame_time).floor() as u32).min(last_frame)
+        };
+
+        // the frames passed are `from..to`, followed by `0..wrapped_to` when looping
+        let (from, to, wrapped_to) = match self.previous_frame {
+            None => (0, frame + 1, 0),
+            Some(previous) if looped => (previous + 1, animation.length, frame + 1),
+            Some(previous) => (previous + 1, frame + 1, 0),
+        };
+
+        for passed in (from..to).chain(0..wrapped_to) {
+            if let Some(events) = animation.events.get(&passed) {
+                self.events.extend(events.iter().cloned());
             }
         }
 
-        let prev = self.frame;
-        self.frame = (self.play_time as f32 / frame_time).floor() as u32;
+        self.just_advanced = from < to || wrapped_to > 0;
+        self.previous_frame = Some(frame);
+        self.frame = frame;
 
-        if self.frame != prev {
-            self.just_advanced = true;
+        if ended {
+            self.stop();
+            self.just_ended = true;
         }
     }
 
@@ -91,25 +282,30 @@ impl Animator {
         self.animation.is_some()
     }
 
-    /// Sets the currently playing animation. This is different from `play`
-    /// because it wont reset the play time.
-    ///
-    /// If `interrupt` is true, then if the currently playing animation is
-    /// different from `animation`, will reset play time.
-    pub fn set_playing(&mut self, animation: impl Into<String>, interrupt: bool) {
-        let animation = animation.into();
-
-        if let Some(current) = &mut self.animation {
-            if *current != animation && interrupt {
-                self.play(animation);
-            } else {
-                *current = animation;
-            }
-        } else {
-            self.play(animation);
+    /// The path of the animation played at the current angle, and whether it's mirrored.
+    pub fn current(&self) -> Option<(&str, bool)> {
+        self.animation
+            .as_ref()
+            .map(|animation| animation.get(self.angle))
+    }
+
+    /// Turns to `angle`, switching to the animation of that angle without restarting it.
+    pub fn set_angle(&mut self, angle: Angle) {
+        let previous = self.current().map(|(path, _)| String::from(path));
+
+        self.angle = angle;
+
+        if self.current().map(|(path, _)| path) != previous.as_deref() {
+            self.texture = None;
         }
     }
 
+    /// Flashes the sprite white, fading out over `duration` seconds.
+    pub fn start_flash(&mut self, duration: f32) {
+        self.flash = 1.0;
+        self.flash_fade = 1.0 / duration.max(f32::EPSILON);
+    }
+
     pub fn just_ended(&self) -> bool {
         self.just_ended
     }
@@ -118,7 +314,7 @@ impl Animator {
         self.just_advanced
     }
 
-    pub fn play(&mut self, animation: impl Into<String>) {
+    pub fn play(&mut self, animation: impl Into<DirectionalAnimation>) {
         self.stop();
         self.animation = Some(animation.into());
     }
@@ -126,6 +322,7 @@ impl Animator {
     pub fn stop(&mut self) {
         self.play_time = 0.0;
         self.frame = 0;
+        self.previous_frame = None;
         self.texture = None;
         self.animation = None;
     }
@@ -138,11 +335,12 @@ pub fn animator_server_system(
     for mut animator in query.iter_mut() {
         animator.just_ended = false;
         animator.just_advanced = false;
+        animator.events.clear();
 
-        if let Some(animation) = animator.animation.clone() {
-            let animation = animations.get(animation.as_str()).unwrap();
+        if let Some((path, _)) = animator.current() {
+            let animation = animations.get(path).unwrap();
 
-            animator.advance(animation, 1.0 / 48.0);
+            animator.advance(animation, 1.0 / TICK_RATE);
         }
     }
 }
@@ -156,10 +354,14 @@ pub fn animator_client_system(
     for mut animator in query.iter_mut() {
         animator.just_ended = false;
         animator.just_advanced = false;
+        animator.events.clear();
+
+        animator.flash = (animator.flash - animator.flash_fade * time.delta_seconds()).max(0.0);
 
-        if let Some(animation) = animator.animation.clone() {
-            let animation = animations.get(animation.as_str()).unwrap();
+        if let Some((path, flip_x)) = animator.current() {
+            let animation = animations.get(path).unwrap();
 
+            animator.flip_x = flip_x as u32;
             animator.advance(animation, time.delta_seconds());
 
             if animator.texture.is_none() {
@@ -221,9 +423,14 @@ impl Plugin for AnimationPlugin {
         app_builder.add_asset_loader(AnimationLoader);
 
         if is_server {
-            app_builder.add_system(animator_server_system.system());
+            app_builder.add_system(animator_server_system.system().label("animator"));
         } else {
-            app_builder.add_system(animator_client_system.system());
+            app_builder.add_system(animator_client_system.system().label("animator"));
+
+            app_builder.insert_resource(CameraShake::default());
+            app_builder.add_system(animation_event_client_system.system().after("animator"));
+            app_builder.add_system(animation_effect_client_system.system().after("animator"));
+            app_builder.add_system(camera_shake_client_system.system());
 
             let mut render_graph = app_builder
                 .world_mut()
@@ -256,3 +463,72 @@ impl Plugin for AnimationPlugin {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// An animation of 4 frames at 10 fps, playing a sound named after every frame.
+    fn animation(looping: bool) -> Animation {
+        Animation {
+            image: String::from("test.png"),
+            columns: 4,
+            rows: 1,
+            length: 4,
+            fps: 10.0,
+            looping,
+            events: (0..4)
+                .map(|frame| (frame, vec![AnimationEvent::PlaySound(frame.to_string())]))
+                .collect(),
+        }
+    }
+
+    /// Advances the animator like the animator systems and returns the sounds fired.
+    fn advance(animator: &mut Animator, animation: &Animation, delta_time: f32) -> Vec<String> {
+        animator.events.clear();
+        animator.advance(animation, delta_time);
+
+        animator
+            .events
+            .iter()
+            .map(|event| match event {
+                AnimationEvent::PlaySound(sound) => sound.clone(),
+                event => panic!("unexpected event {:?}", event),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn skipped_frames_fire_their_events() {
+        let animation = animation(false);
+        let mut animator = Animator::new();
+
+        assert_eq!(advance(&mut animator, &animation, 0.05), ["0"]);
+        assert!(advance(&mut animator, &animation, 0.01).is_empty());
+        assert!(!animator.just_advanced());
+        assert_eq!(advance(&mut animator, &animation, 0.2), ["1", "2"]);
+        assert_eq!(animator.frame(), 2);
+    }
+
+    #[test]
+    fn looping_wraps_around_to_the_first_frame() {
+        let animation = animation(true);
+        let mut animator = Animator::new();
+
+        assert_eq!(advance(&mut animator, &animation, 0.25), ["0", "1", "2"]);
+        assert_eq!(advance(&mut animator, &animation, 0.2), ["3", "0"]);
+        assert_eq!(animator.frame(), 0);
+    }
+
+    #[test]
+    fn ending_fires_the_last_frames() {
+        let animation = animation(false);
+        let mut animator = Animator::new();
+        animator.play("test.anim");
+
+        assert_eq!(advance(&mut animator, &animation, 0.15), ["0", "1"]);
+        assert_eq!(advance(&mut animator, &animation, 0.5), ["2", "3"]);
+        assert!(animator.just_ended());
+        assert!(!animator.is_playing());
+    }
+}
@@ -0,0 +1,133 @@
+//! Cosmetic events of animations, like sounds and camera shake.
+//!
+//! Unlike [`AttackEvent`]s these don't change the game, so every client fires them on its own
+//! when an animation reaches their frame, for remote players as well.
+//!
+//! [`AttackEvent`]: crate::attack::AttackEvent
+
+use crate::animation::*;
+use crate::camera::*;
+use crate::networking::*;
+use crate::transform::*;
+use bevy::prelude::*;
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub enum AnimationEvent {
+    /// Plays the sound at the path.
+    PlaySound(String),
+    /// Plays a non-looping animation at an offset from the animated entity,
+    /// the offset is mirrored with the animation.
+    SpawnEffect { animation: String, offset: Vec2 },
+    /// Shakes the camera up to `strength` units, fading out over `duration` seconds.
+    ShakeCamera { strength: f32, duration: f32 },
+    /// Flashes the sprite white, fading out over `duration` seconds.
+    Flash { duration: f32 },
+}
+
+/// Marks the entities spawned by [`AnimationEvent::SpawnEffect`], despawned when they end.
+pub struct AnimationEffect;
+
+#[derive(Default)]
+pub struct CameraShake {
+    strength: f32,
+    duration: f32,
+    remaining: f32,
+}
+
+impl CameraShake {
+    /// Starts shaking, unless the current shake is stronger.
+    pub fn shake(&mut self, strength: f32, duration: f32) {
+        if strength >= self.current_strength() {
+            self.strength = strength;
+            self.duration = duration;
+            self.remaining = duration;
+        }
+    }
+
+    fn current_strength(&self) -> f32 {
+        if self.duration > 0.0 {
+            self.strength * self.remaining / self.duration
+        } else {
+            0.0
+        }
+    }
+}
+
+pub fn animation_event_client_system(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    audio: Res<Audio>,
+    mut camera_shake: ResMut<CameraShake>,
+    mut query: Query<(&mut Animator, &GlobalTransform)>,
+) {
+    for (mut animator, transform) in query.iter_mut() {
+        // the animation might have ended, the flip of its last frame is kept
+        let flipped = animator.flip_x == 1;
+        let events = std::mem::take(&mut animator.events);
+
+        for event in &events {
+            match event {
+                AnimationEvent::PlaySound(sound) => {
+                    audio.play(asset_server.load(sound.as_str()));
+                }
+                AnimationEvent::SpawnEffect { animation, offset } => {
+                    let offset = if flipped {
+                        Vec2::new(-offset.x, offset.y)
+                    } else {
+                        *offset
+                    };
+
+                    let mut effect = Animator::new();
+                    effect.play(animation.clone());
+
+                    commands
+                        .spawn_bundle(AnimatorBundle {
+                            animator: effect,
+                            transform: Transform::from_translation(
+                                transform.translation + offset.extend(0.0),
+                            ),
+                            ..Default::default()
+                        })
+                        .insert(AnimationEffect)
+                        .insert(ZSort);
+                }
+                AnimationEvent::ShakeCamera { strength, duration } => {
+                    camera_shake.shake(*strength, *duration);
+                }
+                AnimationEvent::Flash { duration } => {
+                    animator.start_flash(*duration);
+                }
+            }
+        }
+    }
+}
+
+pub fn animation_effect_client_system(
+    mut commands: Commands,
+    query: Query<(Entity, &Animator), With<AnimationEffect>>,
+) {
+    for (entity, animator) in query.iter() {
+        if !animator.is_playing() {
+            commands.entity(entity).despawn();
+        }
+    }
+}
+
+pub fn camera_shake_client_system(
+    time: Res<Time>,
+    mut camera_shake: ResMut<CameraShake>,
+    mut query: Query<&mut Transform, With<MainCamera>>,
+) {
+    camera_shake.remaining = (camera_shake.remaining - time.delta_seconds()).max(0.0);
+
+    // a wobble rather than noise, it only has to look shaky
+    let t = time.seconds_since_startup() as f32;
+    let offset = Vec2::new((t * 83.0).sin(), (t * 61.0).cos()) * camera_shake.current_strength();
+
+    // the camera doesn't move otherwise, so it's always shaken around the origin
+    for mut transform in query.iter_mut() {
+        transform.translation.x = offset.x;
+        transform.translation.y = offset.y;
+    }
+}
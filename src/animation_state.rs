@@ -0,0 +1,275 @@
+//! The animation state machine of players.
+//!
+//! Every [`Frame`] points to an [`AnimationStates`] asset with the animation of every state
+//! and the transitions between them. Transitions only look at [`Player`] fields the clients
+//! are sent, so the server and the clients pick the same animations without sending them.
+
+use crate::animation::*;
+use crate::attack::*;
+use crate::frame::*;
+use crate::networking::*;
+use crate::player::*;
+use bevy::prelude::*;
+use std::collections::HashMap;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum AnimationState {
+    Idle,
+    Walk,
+    Run,
+    HitStun,
+    Block,
+    Death,
+    /// Plays the animation of the current attack.
+    Attack,
+}
+
+/// What has to be true about a player for a [`Transition`] to be taken.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub enum Condition {
+    Attacking,
+    Blocking,
+    Stunned,
+    Dead,
+    Moving,
+    /// Moving at least this fast, in units per second.
+    FasterThan(f32),
+    Not(Box<Condition>),
+    All(Vec<Condition>),
+    Any(Vec<Condition>),
+}
+
+impl Condition {
+    pub fn holds(&self, player: &Player, frame: &Frame) -> bool {
+        let moving = player.movement_vector != Vec2::ZERO;
+
+        match self {
+            Condition::Attacking => player.attacking,
+            Condition::Blocking => player.blocking,
+            Condition::Stunned => player.stun.is_some(),
+            Condition::Dead => player.dead,
+            Condition::Moving => moving,
+            Condition::FasterThan(speed) => {
+                moving && frame.walking_speed * player.speed_multiplier >= *speed
+            }
+            Condition::Not(condition) => !condition.holds(player, frame),
+            Condition::All(conditions) => conditions.iter().all(|c| c.holds(player, frame)),
+            Condition::Any(conditions) => conditions.iter().any(|c| c.holds(player, frame)),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Transition {
+    pub to: AnimationState,
+    pub when: Condition,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct AnimationStateData {
+    /// Required for every state but [`AnimationState::Attack`].
+    #[serde(default)]
+    pub animation: Option<DirectionalAnimation>,
+    /// Checked in order, the first one whose condition holds is taken.
+    #[serde(default)]
+    pub transitions: Vec<Transition>,
+}
+
+#[derive(Serialize, Deserialize, TypeUuid)]
+#[serde(deny_unknown_fields)]
+#[uuid = "2cb561a5-0077-4602-ac4a-d6e3db1eb3b5"]
+pub struct AnimationStates {
+    /// Transitions checked in every state, before the transitions of the state.
+    #[serde(default)]
+    pub any_state: Vec<Transition>,
+    /// Players start in [`AnimationState::Idle`], which is required.
+    #[serde(deserialize_with = "crate::helper::unique_map")]
+    pub states: HashMap<AnimationState, AnimationStateData>,
+}
+
+impl AnimationStates {
+    /// Takes transitions from `state` until none of them hold.
+    pub fn next(
+        &self,
+        mut state: AnimationState,
+        player: &Player,
+        frame: &Frame,
+    ) -> AnimationState {
+        // every state is entered at most once, so cycles in the transitions end
+        let mut visited = vec![state];
+
+        loop {
+            let transitions = self.any_state.iter().chain(
+                self.states
+                    .get(&state)
+                    .into_iter()
+                    .flat_map(|data| data.transitions.iter()),
+            );
+
+            let next = transitions
+                .filter(|transition| transition.to != state)
+                .find(|transition| transition.when.holds(player, frame));
+
+            match next {
+                Some(transition) if !visited.contains(&transition.to) => {
+                    state = transition.to;
+                    visited.push(state);
+                }
+                _ => return state,
+            }
+        }
+    }
+}
+
+pub struct AnimationStateController {
+    /// `None` until the first update, and after being reset.
+    pub state: Option<AnimationState>,
+    /// The [`Player::attacks_started`] of the last update, a new attack restarts the attack state.
+    attacks_started: u32,
+}
+
+impl AnimationStateController {
+    pub fn new() -> Self {
+        Self {
+            state: None,
+            attacks_started: 0,
+        }
+    }
+
+    /// Starts over in the idle state, used between rounds.
+    pub fn reset(&mut self) {
+        self.state = None;
+    }
+
+    /// Moves to the next state and plays its animation when the state changes,
+    /// returns the previous state.
+    fn update(
+        &mut self,
+        player: &Player,
+        frame: &Frame,
+        states: &AnimationStates,
+        attacks: &Assets<Attack>,
+        animator: &mut Animator,
+    ) -> Option<AnimationState> {
+        let previous = self.state;
+        let state = states.next(previous.unwrap_or(AnimationState::Idle), player, frame);
+        let new_attack = player.attacks_started != self.attacks_started;
+
+        self.state = Some(state);
+        self.attacks_started = player.attacks_started;
+
+        if previous == Some(state) && !(state == AnimationState::Attack && new_attack) {
+            return previous;
+        }
+
+        let animation = if state == AnimationState::Attack {
+            player
+                .attack
+                .as_ref()
+                .and_then(|path| attacks.get(path.as_str()))
+                .map(|attack| attack.animation.clone())
+        } else {
+            states
+                .states
+                .get(&state)
+                .and_then(|data| data.animation.clone())
+        };
+
+        match animation {
+            Some(animation) => animator.play(animation),
+            None => animator.stop(),
+        }
+
+        previous
+    }
+}
+
+pub fn animation_state_server_system(
+    frames: Res<Assets<Frame>>,
+    animation_states: Res<Assets<AnimationStates>>,
+    attacks: Res<Assets<Attack>>,
+    event_sender: Res<NetworkEventSender>,
+    mut query: Query<(
+        &NetworkEntity,
+        &mut Player,
+        &mut Animator,
+        &mut AnimationStateController,
+        &mut AttackController,
+    )>,
+) {
+    for (network_entity, mut player, mut animator, mut controller, mut attack_controller) in
+        query.iter_mut()
+    {
+        let frame = frames.get(&player.frame).unwrap();
+        let states = animation_states
+            .get(frame.animation_states.as_str())
+            .unwrap();
+
+        let previous = controller.update(&player, frame, states, &attacks, &mut animator);
+
+        // attacks are timed by their animation, so leaving the attack state ends the attack
+        let left_attack = previous == Some(AnimationState::Attack) && controller.state != previous;
+
+        if left_attack && player.attacking {
+            player.attacking = false;
+            attack_controller.stop();
+            event_sender
+                .send(&PlayerEvent::SetAttacking(*network_entity, false))
+                .unwrap();
+        }
+    }
+}
+
+pub fn animation_state_client_system(
+    frames: Res<Assets<Frame>>,
+    animation_states: Res<Assets<AnimationStates>>,
+    attacks: Res<Assets<Attack>>,
+    mut query: Query<(&Player, &mut Animator, &mut AnimationStateController)>,
+) {
+    for (player, mut animator, mut controller) in query.iter_mut() {
+        let frame = frames.get(&player.frame).unwrap();
+        let states = animation_states
+            .get(frame.animation_states.as_str())
+            .unwrap();
+
+        controller.update(player, frame, states, &attacks, &mut animator);
+    }
+}
+
+pub struct AnimationStatesLoader;
+
+crate::ron_loader!(AnimationStatesLoader, "states" => AnimationStates);
+
+pub struct AnimationStatePlugin;
+
+impl Plugin for AnimationStatePlugin {
+    fn build(&self, app_builder: &mut AppBuilder) {
+        let is_server = app_builder
+            .world()
+            .get_resource::<NetworkSettings>()
+            .unwrap()
+            .is_server;
+
+        app_builder.add_asset::<AnimationStates>();
+        app_builder.add_asset_loader(AnimationStatesLoader);
+
+        // after the player systems change the player, before the animators advance
+        if is_server {
+            app_builder.add_system(
+                animation_state_server_system
+                    .system()
+                    .after("player")
+                    .before("animator"),
+            );
+        } else {
+            app_builder.add_system(
+                animation_state_client_system
+                    .system()
+                    .after("player")
+                    .before("animator"),
+            );
+        }
+    }
+}
@@ -0,0 +1,658 @@
+//! Validation of the asset files, run with `--check-assets`.
+//!
+//! Every asset is parsed the same way the loaders parse them, after which
+//! the references between assets are checked, so broken assets are caught
+//! before they break at runtime.
+
+use crate::animation::*;
+use crate::animation_event::*;
+use crate::animation_state::*;
+use crate::attack::*;
+use crate::bot::*;
+use crate::dungeon::*;
+use crate::enemy::*;
+use crate::frame::*;
+use crate::game_settings::*;
+use crate::input::*;
+use crate::item::*;
+use crate::map::*;
+use crate::progress_bar::*;
+use crate::status_effect::*;
+use bevy::prelude::*;
+use serde::de::DeserializeOwned;
+use std::collections::HashMap;
+use std::fmt::Display;
+use std::path::{Path, PathBuf};
+
+/// Extensions of files that aren't parsed, but can be referenced.
+const IGNORED_EXTENSIONS: &[&str] = &["png", "ttf", "vert", "frag", "mp3"];
+
+/// Parses and cross-checks all assets in a folder.
+pub struct AssetChecker {
+    root: PathBuf,
+    files: Vec<String>,
+    frames: HashMap<String, Frame>,
+    attacks: HashMap<String, Attack>,
+    animations: HashMap<String, Animation>,
+    animation_states: HashMap<String, AnimationStates>,
+    status_effects: HashMap<String, StatusEffect>,
+    bot_difficulties: HashMap<String, BotDifficulty>,
+    enemies: HashMap<String, Enemy>,
+    items: HashMap<String, Item>,
+    maps: HashMap<String, Map>,
+    errors: Vec<String>,
+}
+
+impl AssetChecker {
+    pub fn new(root: impl Into<PathBuf>) -> Self {
+        Self {
+            root: root.into(),
+            files: Vec::new(),
+            frames: HashMap::new(),
+            attacks: HashMap::new(),
+            animations: HashMap::new(),
+            animation_states: HashMap::new(),
+            status_effects: HashMap::new(),
+            bot_difficulties: HashMap::new(),
+            enemies: HashMap::new(),
+            items: HashMap::new(),
+            maps: HashMap::new(),
+            errors: Vec::new(),
+        }
+    }
+
+    pub fn errors(&self) -> &[String] {
+        &self.errors
+    }
+
+    pub fn frames(&self) -> &HashMap<String, Frame> {
+        &self.frames
+    }
+
+    pub fn attacks(&self) -> &HashMap<String, Attack> {
+        &self.attacks
+    }
+
+    pub fn animations(&self) -> &HashMap<String, Animation> {
+        &self.animations
+    }
+
+    pub fn bot_difficulties(&self) -> &HashMap<String, BotDifficulty> {
+        &self.bot_difficulties
+    }
+
+    pub fn enemies(&self) -> &HashMap<String, Enemy> {
+        &self.enemies
+    }
+
+    /// Adds the parsed assets to the world with their paths as handles,
+    /// as if they were loaded by the [`AssetServer`].
+    pub fn insert_assets(self, world: &mut World) {
+        fn insert<T: bevy::asset::Asset>(world: &mut World, assets: HashMap<String, T>) {
+            // the world might not use every kind of asset
+            if let Some(mut world_assets) = world.get_resource_mut::<Assets<T>>() {
+                for (path, asset) in assets {
+                    world_assets.set_untracked(path.as_str(), asset);
+                }
+            }
+        }
+
+        insert(world, self.frames);
+        insert(world, self.attacks);
+        insert(world, self.animations);
+        insert(world, self.animation_states);
+        insert(world, self.status_effects);
+        insert(world, self.bot_difficulties);
+        insert(world, self.enemies);
+        insert(world, self.items);
+        insert(world, self.maps);
+    }
+
+    fn error(&mut self, path: &str, message: impl Display) {
+        self.errors.push(format!("{}: {}", path, message));
+    }
+
+    /// Parses every asset and checks the references between them.
+    pub fn check(&mut self) -> std::io::Result<()> {
+        let root = self.root.clone();
+        self.collect_files(&root)?;
+        self.files.sort();
+
+        for path in self.files.clone() {
+            self.parse(&path)?;
+        }
+
+        self.check_references();
+
+        Ok(())
+    }
+
+    fn collect_files(&mut self, dir: &Path) -> std::io::Result<()> {
+        for entry in std::fs::read_dir(dir)? {
+            let path = entry?.path();
+
+            if path.is_dir() {
+                self.collect_files(&path)?;
+            } else {
+                // asset paths always use forward slashes
+                let relative = path
+                    .strip_prefix(&self.root)
+                    .unwrap()
+                    .components()
+                    .map(|component| component.as_os_str().to_string_lossy())
+                    .collect::<Vec<_>>()
+                    .join("/");
+
+                self.files.push(relative);
+            }
+        }
+
+        Ok(())
+    }
+
+    fn parse(&mut self, path: &str) -> std::io::Result<()> {
+        let extension = Path::new(path)
+            .extension()
+            .map(|extension| extension.to_string_lossy().into_owned())
+            .unwrap_or_default();
+
+        if IGNORED_EXTENSIONS.contains(&extension.as_str()) {
+            return Ok(());
+        }
+
+        let bytes = std::fs::read(self.root.join(path))?;
+
+        match extension.as_str() {
+            "fme" => {
+                if let Some(frame) = self.parse_ron::<Frame>(path, &bytes) {
+                    self.frames.insert(String::from(path), frame);
+                }
+            }
+            "atk" => {
+                if let Some(attack) = self.parse_ron::<Attack>(path, &bytes) {
+                    self.attacks.insert(String::from(path), attack);
+                }
+            }
+            "anim" => {
+                if let Some(animation) = self.parse_ron::<Animation>(path, &bytes) {
+                    self.animations.insert(String::from(path), animation);
+                }
+            }
+            "states" => {
+                if let Some(states) = self.parse_ron::<AnimationStates>(path, &bytes) {
+                    self.animation_states.insert(String::from(path), states);
+                }
+            }
+            "eff" => {
+                if let Some(status_effect) = self.parse_ron::<StatusEffect>(path, &bytes) {
+                    self.status_effects
+                        .insert(String::from(path), status_effect);
+                }
+            }
+            "inp" => {
+                self.parse_ron::<InputSettings>(path, &bytes);
+            }
+            "pb" => {
+                self.parse_ron::<ProgressBarMaterial>(path, &bytes);
+            }
+            "bot" => {
+                if let Some(difficulty) = self.parse_ron::<BotDifficulty>(path, &bytes) {
+                    self.bot_difficulties.insert(String::from(path), difficulty);
+                }
+            }
+            "enm" => {
+                if let Some(enemy) = self.parse_ron::<Enemy>(path, &bytes) {
+                    self.enemies.insert(String::from(path), enemy);
+                }
+            }
+            "itm" => {
+                if let Some(item) = self.parse_ron::<Item>(path, &bytes) {
+                    self.items.insert(String::from(path), item);
+                }
+            }
+            "map" => {
+                if let Some(map) = self.parse_ron::<Map>(path, &bytes) {
+                    self.maps.insert(String::from(path), map);
+                }
+            }
+            e => self.error(path, format!("unknown asset extension '{}'", e)),
+        }
+
+        Ok(())
+    }
+
+    fn parse_ron<T: DeserializeOwned>(&mut self, path: &str, bytes: &[u8]) -> Option<T> {
+        match crate::helper::parse_ron(bytes) {
+            Ok(asset) => Some(asset),
+            Err(e) => {
+                self.error(path, e);
+                None
+            }
+        }
+    }
+
+    fn check_references(&mut self) {
+        let mut errors = Vec::new();
+
+        for (path, frame) in &self.frames {
+            check_exists(
+                &mut errors,
+                path,
+                self.animation_states.contains_key(&frame.animation_states),
+                "animation state machine",
+                &frame.animation_states,
+            );
+
+            check_exists(
+                &mut errors,
+                path,
+                self.attacks.contains_key(&frame.light_attack),
+                "attack",
+                &frame.light_attack,
+            );
+        }
+
+        for (path, states) in &self.animation_states {
+            if !states.states.contains_key(&AnimationState::Idle) {
+                errors.push(format!("{}: there's no Idle state to start in", path));
+            }
+
+            let transitions = states
+                .any_state
+                .iter()
+                .chain(states.states.values().flat_map(|data| &data.transitions));
+
+            for transition in transitions {
+                if !states.states.contains_key(&transition.to) {
+                    errors.push(format!(
+                        "{}: transition to the missing state {:?}",
+                        path, transition.to
+                    ));
+                }
+            }
+
+            for (state, data) in &states.states {
+                match (&data.animation, state) {
+                    (Some(animation), _) => {
+                        check_directional(&mut errors, path, &self.animations, animation);
+                    }
+                    (None, AnimationState::Attack) => (),
+                    (None, _) => {
+                        errors.push(format!("{}: the {:?} state has no animation", path, state))
+                    }
+                }
+            }
+        }
+
+        for (path, attack) in &self.attacks {
+            let length = check_directional(&mut errors, path, &self.animations, &attack.animation)
+                .map(|a| a.length);
+
+            for (event_frame, events) in &attack.events {
+                if let Some(length) = length {
+                    if *event_frame >= length {
+                        errors.push(format!(
+                            "{}: event frame {} is outside the animation, which is {} frames long",
+                            path, event_frame, length
+                        ));
+                    }
+                }
+
+                for event in events {
+                    let (animation, hitbox, effects) = match event {
+                        AttackEvent::ActivateHitbox {
+                            animation,
+                            hitbox,
+                            effects,
+                            ..
+                        } => (animation, hitbox, effects),
+                        AttackEvent::SpawnProjectile {
+                            animation,
+                            hitbox,
+                            effects,
+                            ..
+                        } => (animation, hitbox, effects),
+                        _ => continue,
+                    };
+
+                    check_exists(
+                        &mut errors,
+                        path,
+                        self.animations.contains_key(animation),
+                        "animation",
+                        animation,
+                    );
+
+                    for effect in effects {
+                        check_exists(
+                            &mut errors,
+                            path,
+                            self.status_effects.contains_key(effect),
+                            "status effect",
+                            effect,
+                        );
+                    }
+
+                    if !is_convex(hitbox) {
+                        errors.push(format!(
+                            "{}: hitbox on frame {} is not a convex polygon: {:?}",
+                            path, event_frame, hitbox
+                        ));
+                    }
+                }
+            }
+        }
+
+        for (path, animation) in &self.animations {
+            check_exists(
+                &mut errors,
+                path,
+                self.files.contains(&animation.image),
+                "image",
+                &animation.image,
+            );
+
+            if animation.columns * animation.rows < animation.length {
+                errors.push(format!(
+                    "{}: {} frames don't fit in {} columns and {} rows",
+                    path, animation.length, animation.columns, animation.rows
+                ));
+            }
+
+            if animation.fps <= 0.0 {
+                errors.push(format!("{}: fps must be positive", path));
+            }
+
+            for (event_frame, events) in &animation.events {
+                if *event_frame >= animation.length {
+                    errors.push(format!(
+                        "{}: event frame {} is outside the animation, which is {} frames long",
+                        path, event_frame, animation.length
+                    ));
+                }
+
+                for event in events {
+                    match event {
+                        AnimationEvent::PlaySound(sound) => {
+                            check_exists(
+                                &mut errors,
+                                path,
+                                self.files.contains(sound),
+                                "sound",
+                                sound,
+                            );
+                        }
+                        AnimationEvent::SpawnEffect { animation, .. } => {
+                            check_exists(
+                                &mut errors,
+                                path,
+                                self.animations.contains_key(animation),
+                                "animation",
+                                animation,
+                            );
+
+                            // looping effects would never be despawned
+                            if self.animations.get(animation).map_or(false, |a| a.looping) {
+                                errors.push(format!(
+                                    "{}: the effect '{}' is looping",
+                                    path, animation
+                                ));
+                            }
+                        }
+                        AnimationEvent::ShakeCamera { duration, .. }
+                        | AnimationEvent::Flash { duration } => {
+                            if *duration <= 0.0 {
+                                errors.push(format!("{}: durations must be positive", path));
+                            }
+                        }
+                    }
+                }
+            }
+        }
+
+        for (path, status_effect) in &self.status_effects {
+            check_exists(
+                &mut errors,
+                path,
+                self.files.contains(&status_effect.icon),
+                "icon",
+                &status_effect.icon,
+            );
+        }
+
+        for (path, enemy) in &self.enemies {
+            check_exists(
+                &mut errors,
+                path,
+                self.frames.contains_key(&enemy.frame),
+                "frame",
+                &enemy.frame,
+            );
+
+            check_loot(&mut errors, path, &self.items, &enemy.loot);
+        }
+
+        // the chests aren't assets, but drop items that are
+        check_loot(
+            &mut errors,
+            "dungeon chests",
+            &self.items,
+            &DungeonParams::default().chest_loot,
+        );
+
+        for (path, item) in &self.items {
+            check_exists(
+                &mut errors,
+                path,
+                self.files.contains(&item.icon),
+                "icon",
+                &item.icon,
+            );
+
+            for modifier in &item.modifiers {
+                if let ItemModifier::LightAttack(attack) = modifier {
+                    check_exists(
+                        &mut errors,
+                        path,
+                        self.attacks.contains_key(attack),
+                        "attack",
+                        attack,
+                    );
+                }
+            }
+        }
+
+        for (path, map) in &self.maps {
+            check_exists(
+                &mut errors,
+                path,
+                self.files.contains(&map.background),
+                "image",
+                &map.background,
+            );
+
+            if !map.bounds.min.cmplt(map.bounds.max).all() {
+                errors.push(format!("{}: the bounds are empty", path));
+            }
+
+            for (i, collider) in map.colliders.iter().enumerate() {
+                if !is_convex(collider) {
+                    errors.push(format!(
+                        "{}: collider {} is not a convex polygon: {:?}",
+                        path, i, collider
+                    ));
+                }
+            }
+
+            for (mode, spawn_points) in &map.spawn_points {
+                if spawn_points.len() < mode.players() {
+                    errors.push(format!(
+                        "{}: {:?} needs {} spawn points, but has {}",
+                        path,
+                        mode,
+                        mode.players(),
+                        spawn_points.len()
+                    ));
+                }
+
+                for spawn_point in spawn_points {
+                    if !map.bounds.contains(*spawn_point) {
+                        errors.push(format!(
+                            "{}: the {:?} spawn point {} is outside the bounds",
+                            path, mode, spawn_point
+                        ));
+                    }
+                }
+            }
+        }
+
+        // the map isn't referenced by other assets, but by the config
+        check_exists(
+            &mut errors,
+            "default map",
+            self.maps.contains_key(DEFAULT_MAP),
+            "map",
+            DEFAULT_MAP,
+        );
+
+        errors.sort();
+        self.errors.append(&mut errors);
+    }
+}
+
+fn check_exists(errors: &mut Vec<String>, path: &str, exists: bool, kind: &str, reference: &str) {
+    if !exists {
+        errors.push(format!("{}: {} '{}' doesn't exist", path, kind, reference));
+    }
+}
+
+/// Checks every angle of a directional animation exists and they're all played the same,
+/// since the animator switches angles without restarting.
+///
+/// Returns the animation of any angle, if they all exist.
+fn check_directional<'a>(
+    errors: &mut Vec<String>,
+    path: &str,
+    animations: &'a HashMap<String, Animation>,
+    animation: &DirectionalAnimation,
+) -> Option<&'a Animation> {
+    let paths = animation.paths();
+
+    if paths.is_empty() {
+        errors.push(format!("{}: a directional animation has no angles", path));
+        return None;
+    }
+
+    for reference in &paths {
+        check_exists(
+            errors,
+            path,
+            animations.contains_key(*reference),
+            "animation",
+            reference,
+        );
+    }
+
+    let found = paths
+        .iter()
+        .filter_map(|reference| animations.get(*reference))
+        .collect::<Vec<_>>();
+
+    if found.len() != paths.len() {
+        return None;
+    }
+
+    let first = found[0];
+
+    if found
+        .iter()
+        .any(|other| other.length != first.length || other.fps != first.fps)
+    {
+        errors.push(format!(
+            "{}: the angles of {:?} don't all have the same length and fps",
+            path, paths
+        ));
+    }
+
+    Some(first)
+}
+
+fn check_loot(
+    errors: &mut Vec<String>,
+    path: &str,
+    items: &HashMap<String, Item>,
+    loot: &[LootDrop],
+) {
+    for drop in loot {
+        check_exists(
+            errors,
+            path,
+            items.contains_key(&drop.item),
+            "item",
+            &drop.item,
+        );
+
+        if !(0.0..=1.0).contains(&drop.chance) {
+            errors.push(format!(
+                "{}: the chance of dropping '{}' must be between 0 and 1",
+                path, drop.item
+            ));
+        }
+    }
+}
+
+/// Returns true if the points form a convex polygon, in the order given.
+pub fn is_convex(points: &[Vec2]) -> bool {
+    if points.len() < 3 {
+        return false;
+    }
+
+    let mut sign = 0.0;
+    // turning the same way at every corner isn't enough, a star does that too,
+    // a polygon that doesn't cross itself turns around exactly once
+    let mut turned = 0.0;
+
+    for i in 0..points.len() {
+        let a = points[i];
+        let b = points[(i + 1) % points.len()];
+        let c = points[(i + 2) % points.len()];
+
+        let cross = (b - a).perp_dot(c - b);
+        turned += cross.atan2((b - a).dot(c - b));
+
+        if cross == 0.0 {
+            continue;
+        }
+
+        if sign == 0.0 {
+            sign = cross.signum();
+        } else if cross.signum() != sign {
+            return false;
+        }
+    }
+
+    // all points on a line
+    sign != 0.0 && (turned.abs() - std::f32::consts::TAU).abs() < 1e-3
+}
+
+/// Checks the assets in `root`, prints the errors and returns the number of errors found.
+pub fn check_assets(root: &str) -> usize {
+    let mut checker = AssetChecker::new(root);
+
+    if let Err(e) = checker.check() {
+        eprintln!("failed to read '{}': {}", root, e);
+        return 1;
+    }
+
+    for error in checker.errors() {
+        eprintln!("{}", error);
+    }
+
+    if checker.errors().is_empty() {
+        println!("All {} asset files are valid", checker.files.len());
+    } else {
+        eprintln!("{} errors found", checker.errors().len());
+    }
+
+    checker.errors().len()
+}
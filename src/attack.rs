@@ -1,65 +1,191 @@
 use crate::animation::*;
+use crate::frame::*;
+use crate::game_settings::*;
 use crate::networking::*;
 use crate::player::*;
+use crate::status_effect::*;
 use crate::transform::*;
 use bevy::prelude::*;
 use std::collections::HashMap;
 use heron::prelude::*;
 
-#[derive(Serialize, Deserialize)]
+#[derive(Serialize, Deserialize, Debug, Clone, Copy)]
 pub enum AttackType {
     LightAttack,
 }
 
+impl AttackType {
+    pub const ALL: [AttackType; 1] = [AttackType::LightAttack];
+}
+
 #[derive(Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
 pub enum AttackEvent {
     /// Activates the hitbox and sets it.
     ActivateHitbox {
         stun: u32,
         damage: f32,
+        #[serde(default)]
+        push_back: f32,
+        #[serde(default)]
+        block_stun: u32,
+        #[serde(default)]
+        guard_damage: f32,
+        /// Paths to the status effects applied on hit.
+        #[serde(default)]
+        effects: Vec<String>,
+        animation: String,
+        hitbox: Vec<Vec2>,
+    },
+    /// Spawns a projectile traveling in the aim direction.
+    SpawnProjectile {
+        stun: u32,
+        damage: f32,
+        #[serde(default)]
         push_back: f32,
+        #[serde(default)]
+        block_stun: u32,
+        #[serde(default)]
+        guard_damage: f32,
+        /// Paths to the status effects applied on hit.
+        #[serde(default)]
+        effects: Vec<String>,
+        /// The speed in units per second.
+        speed: f32,
+        /// The number of frames before the projectile is despawned.
+        lifetime: u32,
+        /// The number of players the projectile can pass through.
+        #[serde(default)]
+        pierce: u32,
+        /// Should be looping, since the projectile is despawned when a non-looping animation ends.
         animation: String,
         hitbox: Vec<Vec2>,
     },
     /// Stuns the player.
     Stun(u32),
+    /// Adds an impulse to the players velocity, relative to the aim direction
+    /// like hitboxes, so positive y is forward.
+    Impulse(Vec2),
+    /// Sets the players velocity relative to the aim direction for a number of frames.
+    SetVelocity { velocity: Vec2, frames: u32 },
+    /// Limits how fast the player can turn, in radians per frame, for a number of frames.
+    SetTurnSpeed { turn_speed: f32, frames: u32 },
 }
 
 #[derive(Serialize, Deserialize, TypeUuid)]
+#[serde(deny_unknown_fields)]
 #[uuid = "219b96a9-7102-4102-9c5d-ca9e7e6b3dbb"]
 pub struct Attack {
-    pub animation: String,
+    pub animation: DirectionalAnimation,
+    #[serde(default)]
+    pub stamina_cost: f32,
+    /// If true, the player can't walk while the attack is active.
+    #[serde(default)]
+    pub lock_movement: bool,
+    #[serde(deserialize_with = "crate::helper::unique_map")]
     pub events: HashMap<u32, Vec<AttackEvent>>,
 }
 
+impl Attack {
+    /// How far in front of the player the attack reaches.
+    pub fn reach(&self) -> f32 {
+        self.events
+            .values()
+            .flatten()
+            .map(|event| match event {
+                AttackEvent::ActivateHitbox { hitbox, .. } => hitbox
+                    .iter()
+                    .map(|point| point.length())
+                    .fold(0.0, f32::max),
+                AttackEvent::SpawnProjectile {
+                    speed, lifetime, ..
+                } => speed * *lifetime as f32 / crate::server::TICK_RATE,
+                _ => 0.0,
+            })
+            .fold(0.0, f32::max)
+    }
+}
+
 #[derive(Clone, Serialize, Deserialize)]
 pub struct Damage {
     pub source: NetworkEntity,
+    /// The team of the source, so teammates can't hurt each other.
+    pub team: Team,
+    /// The path of the attack that caused the damage.
+    pub attack: String,
     pub stun: u32,
     pub damage: f32,
     pub push_back: f32,
+    /// The stun applied instead of `stun` when the hit is blocked.
+    pub block_stun: u32,
+    pub guard_damage: f32,
+    /// Paths to the status effects applied when the hit isn't blocked.
+    pub effects: Vec<String>,
+}
+
+/// Sent on the server when a player starts an attack.
+pub struct AttackStartEvent {
+    pub attacker: NetworkEntity,
+    pub attack: String,
+}
+
+/// Sent on the server when a [`Damage`] connects with a player.
+pub struct HitEvent {
+    pub source: NetworkEntity,
+    pub target: NetworkEntity,
+    pub attack: String,
+    pub result: HitResult,
+    /// The health the target lost.
+    pub damage: f32,
+}
+
+pub struct Projectile {
+    pub lifetime: u32,
+    pub pierce: u32,
 }
 
 pub struct AttackController {
     pub attack: Option<Handle<Attack>>,
+    /// The path of the current attack.
+    pub path: Option<String>,
+    /// The velocity set by [`AttackEvent::SetVelocity`] and the frames left.
+    pub velocity: Option<(Vec2, u32)>,
+    /// The turn speed set by [`AttackEvent::SetTurnSpeed`] and the frames left.
+    pub turn_speed: Option<(f32, u32)>,
 }
 
 impl AttackController {
     pub fn new() -> Self {
-        Self { attack: None }
+        Self {
+            attack: None,
+            path: None,
+            velocity: None,
+            turn_speed: None,
+        }
     }
 
-    pub fn attack(&mut self, attack: Handle<Attack>) {
+    pub fn attack(&mut self, path: String, attack: Handle<Attack>) {
         self.attack = Some(attack);
+        self.path = Some(path);
     }
 
     pub fn stop(&mut self) {
         self.attack = None;
+        self.path = None;
+        self.velocity = None;
+        self.turn_speed = None;
     }
 }
 
+/// The rotation from attack space, where positive y is forward, to world space.
+pub fn aim_rotation(direction: Vec2) -> Quat {
+    // the aim direction points away from the target
+    Quat::from_rotation_z(direction.y.atan2(direction.x) + std::f32::consts::PI / 2.0)
+}
+
 pub fn attack_server_system(
     attacks: Res<Assets<Attack>>,
+    frames: Res<Assets<Frame>>,
     network_spawner: Res<NetworkSpawner>,
     mut query: Query<(
         Entity,
@@ -68,10 +194,18 @@ pub fn attack_server_system(
         &mut Player,
         &Animator,
         &Transform,
+        &mut Velocity,
     )>,
 ) {
-    for (entity, network_entity, mut attack_controller, mut player, animator, world_transform) in
-        query.iter_mut()
+    for (
+        entity,
+        network_entity,
+        mut attack_controller,
+        mut player,
+        animator,
+        world_transform,
+        mut velocity,
+    ) in query.iter_mut()
     {
         if attack_controller.attack.is_some() && animator.just_ended() {
             attack_controller.stop();
@@ -81,6 +215,8 @@ pub fn attack_server_system(
         if animator.just_advanced() {
             if let Some(attack_handle) = attack_controller.attack.clone() {
                 let attack = attacks.get(attack_handle).unwrap();
+                let attack_path = attack_controller.path.clone().unwrap_or_default();
+                let damage_multiplier = frames.get(&player.frame).unwrap().damage_multiplier;
 
                 let frame = animator.frame();
 
@@ -92,6 +228,9 @@ pub fn attack_server_system(
                                 stun,
                                 damage,
                                 push_back,
+                                block_stun,
+                                guard_damage,
+                                effects,
                                 animation,
                                 hitbox,
                             } => {
@@ -100,9 +239,14 @@ pub fn attack_server_system(
                                     animation: animation.clone(),
                                     damage: Damage {
                                         source: *network_entity,
+                                        team: player.team,
+                                        attack: attack_path.clone(),
                                         stun: *stun,
-                                        damage: *damage,
+                                        damage: *damage * damage_multiplier,
                                         push_back: *push_back,
+                                        block_stun: *block_stun,
+                                        guard_damage: *guard_damage,
+                                        effects: effects.clone(),
                                     },
                                     direction: player.aim_direction,
                                     hitbox: hitbox.clone(),
@@ -111,10 +255,67 @@ pub fn attack_server_system(
                                 network_spawner.spawn(spawner);
                             }
 
+                            AttackEvent::SpawnProjectile {
+                                stun,
+                                damage,
+                                push_back,
+                                block_stun,
+                                guard_damage,
+                                effects,
+                                speed,
+                                lifetime,
+                                pierce,
+                                animation,
+                                hitbox,
+                            } => {
+                                let spawner = ProjectileSpawner {
+                                    animation: animation.clone(),
+                                    damage: Damage {
+                                        source: *network_entity,
+                                        team: player.team,
+                                        attack: attack_path.clone(),
+                                        stun: *stun,
+                                        damage: *damage * damage_multiplier,
+                                        push_back: *push_back,
+                                        block_stun: *block_stun,
+                                        guard_damage: *guard_damage,
+                                        effects: effects.clone(),
+                                    },
+                                    position: world_transform.translation.truncate(),
+                                    direction: player.aim_direction,
+                                    speed: *speed,
+                                    lifetime: *lifetime,
+                                    pierce: *pierce,
+                                    hitbox: hitbox.clone(),
+                                };
+
+                                network_spawner.spawn(spawner);
+                            }
+
                             AttackEvent::Stun(duration) => {
                                 player.stun(*duration);
                                 info!("stunning player");
                             }
+
+                            AttackEvent::Impulse(impulse) => {
+                                let rotation = aim_rotation(player.aim_direction);
+                                velocity.linear += rotation * impulse.extend(0.0);
+                            }
+
+                            AttackEvent::SetVelocity {
+                                velocity: attack_velocity,
+                                frames,
+                            } => {
+                                let rotation = aim_rotation(player.aim_direction);
+                                let attack_velocity =
+                                    (rotation * attack_velocity.extend(0.0)).truncate();
+
+                                attack_controller.velocity = Some((attack_velocity, *frames));
+                            }
+
+                            AttackEvent::SetTurnSpeed { turn_speed, frames } => {
+                                attack_controller.turn_speed = Some((*turn_speed, *frames));
+                            }
                         }
                     }
                 }
@@ -125,19 +326,144 @@ pub fn attack_server_system(
 
 pub fn attack_hit_server_system(
     mut events: EventReader<CollisionEvent>,
+    mut hit_events: EventWriter<HitEvent>,
+    frames: Res<Assets<Frame>>,
+    status_effects: Res<Assets<StatusEffect>>,
+    game_settings: Res<GameSettings>,
+    network_entity_registry: Res<NetworkEntityRegistry>,
+    event_sender: Res<NetworkEventSender>,
     query: Query<(&Damage, &GlobalTransform)>,
-    mut player_query: Query<(&NetworkEntity, &mut Player, &GlobalTransform, &mut Velocity)>,
+    mut player_query: Query<(
+        &NetworkEntity,
+        &mut Player,
+        &mut AttackController,
+        &mut StatusEffects,
+        &GlobalTransform,
+        &mut Velocity,
+    )>,
 ) {
     let mut handle = move |a, b| {
         if let Ok((damage, damage_transform)) = query.get(a) {
-            if let Ok((network_entity, mut player, player_transform, mut velocity)) = player_query.get_mut(b) {
-                if *network_entity != damage.source {
-                    player.hit(damage);
+            let mut parry_stun = None;
+
+            if let Ok((
+                network_entity,
+                mut player,
+                _,
+                mut active_effects,
+                player_transform,
+                mut velocity,
+            )) = player_query.get_mut(b)
+            {
+                if *network_entity == damage.source || player.dead {
+                    return;
+                }
+
+                if player.team == damage.team && !game_settings.friendly_fire {
+                    return;
+                }
+
+                let frame = frames.get(&player.frame).unwrap();
+                let diff = player_transform.translation - damage_transform.translation;
+
+                // the aim direction points away from where the player is facing
+                let from_front = diff.truncate().dot(player.aim_direction) > 0.0;
+
+                let health = player.health;
+                let result = player.receive_hit(damage, from_front, frame);
+
+                hit_events.send(HitEvent {
+                    source: damage.source,
+                    target: *network_entity,
+                    attack: damage.attack.clone(),
+                    result,
+                    damage: health - player.health,
+                });
+
+                match result {
+                    HitResult::Parried => parry_stun = Some(frame.parry_stun),
+                    _ => velocity.linear += diff.normalize() * damage.push_back,
+                }
+
+                if result == HitResult::Hit || result == HitResult::GuardBreak {
+                    for path in &damage.effects {
+                        let effect = status_effects.get(path.as_str()).unwrap();
+                        active_effects.apply(path, effect);
+                    }
+                }
+            }
+
+            // stun the attacker and cancel their attack
+            if let Some(stun) = parry_stun {
+                if let Some(source) = network_entity_registry.get(&damage.source) {
+                    if let Ok((_, mut attacker, mut attack_controller, _, _, _)) =
+                        player_query.get_mut(source)
+                    {
+                        attacker.stun(stun);
+                        attack_controller.stop();
+
+                        if attacker.attacking {
+                            attacker.attacking = false;
+                            event_sender
+                                .send(&PlayerEvent::SetAttacking(damage.source, false))
+                                .unwrap();
+                        }
+                    }
+                }
+            }
+        }
+    };
+
+    for event in events.iter() {
+        match event {
+            CollisionEvent::Started(a, b) => {
+                handle(*a, *b);
+                handle(*b, *a);
+            }
+            _ => (),
+        }
+    }
+}
+
+/// Ticks down projectile lifetimes and despawns projectiles that hit something.
+pub fn projectile_server_system(
+    network_spawner: Res<NetworkSpawner>,
+    mut events: EventReader<CollisionEvent>,
+    mut query: Query<(&NetworkEntity, &mut Projectile, &Damage)>,
+    player_query: Query<&NetworkEntity, With<Player>>,
+    damage_query: Query<&Damage>,
+) {
+    let mut despawned = Vec::new();
+
+    for (network_entity, mut projectile, _) in query.iter_mut() {
+        if projectile.lifetime == 0 {
+            despawned.push(*network_entity);
+        } else {
+            projectile.lifetime -= 1;
+        }
+    }
+
+    let mut handle = |a, b| {
+        if let Ok((network_entity, mut projectile, damage)) = query.get_mut(a) {
+            // projectiles pass through other hitboxes
+            if damage_query.get(b).is_ok() {
+                return;
+            }
+
+            if let Ok(player) = player_query.get(b) {
+                if *player == damage.source {
+                    return;
+                }
 
-                    let diff = player_transform.translation - damage_transform.translation;
-                    velocity.linear += diff.normalize() * damage.push_back;
+                if projectile.pierce > 0 {
+                    projectile.pierce -= 1;
+                    return;
                 }
             }
+
+            if !despawned.contains(network_entity) {
+                despawned.push(*network_entity);
+            }
         }
     };
 
@@ -150,11 +476,15 @@ pub fn attack_hit_server_system(
             _ => (),
         }
     }
+
+    for network_entity in despawned {
+        network_spawner.despawn(network_entity);
+    }
 }
 
 pub fn attack_hit_despawn_system(
     mut commands: Commands,
-    query: Query<(Entity, &Animator), With<Damage>>,
+    query: Query<(Entity, &Animator), (With<Damage>, Without<Projectile>)>,
 ) {
     for (entity, animator) in query.iter() {
         if animator.just_ended() {
@@ -183,8 +513,7 @@ impl NetworkSpawnable for AttackHitSpawner {
             .get(&self.parent)
             .unwrap();
 
-        let rotation = self.direction.y.atan2(self.direction.x) + std::f32::consts::PI / 2.0;
-        let transform = Transform::from_rotation(Quat::from_rotation_z(rotation));
+        let transform = Transform::from_rotation(aim_rotation(self.direction));
 
         let body = crate::helper::convex_hull(self.hitbox.clone());
         let body_type = BodyType::Sensor;
@@ -212,12 +541,77 @@ impl NetworkSpawnable for AttackHitSpawner {
                     ..Default::default()
                 })
                 .insert(self.damage.clone())
+                .insert(body)
                 .insert(Parent(parent))
                 .id()
         }
     }
 }
 
+#[derive(Clone, Serialize, Deserialize, TypeUuid)]
+#[uuid = "a6c4d2e8-51f7-4b39-9e0a-7c3b8d1f2e65"]
+pub struct ProjectileSpawner {
+    pub animation: String,
+    pub damage: Damage,
+    pub position: Vec2,
+    pub direction: Vec2,
+    pub speed: f32,
+    pub lifetime: u32,
+    pub pierce: u32,
+    pub hitbox: Vec<Vec2>,
+}
+
+impl NetworkSpawnable for ProjectileSpawner {
+    fn spawn(&self, world: &mut World) -> Entity {
+        let is_server = world.get_resource::<NetworkSettings>().unwrap().is_server;
+
+        let transform = Transform {
+            translation: self.position.extend(0.0),
+            rotation: aim_rotation(self.direction),
+            ..Default::default()
+        };
+
+        let mut animator = Animator::new();
+        animator.play(self.animation.clone());
+
+        if is_server {
+            let velocity = (aim_rotation(self.direction) * Vec3::Y).truncate() * self.speed;
+
+            world
+                .spawn()
+                .insert(self.damage.clone())
+                .insert(Projectile {
+                    lifetime: self.lifetime,
+                    pierce: self.pierce,
+                })
+                .insert(animator)
+                .insert(crate::helper::convex_hull(self.hitbox.clone()))
+                .insert(BodyType::Sensor)
+                .insert(Velocity::from_linear(velocity.extend(0.0)))
+                .insert(RotationConstraints::lock())
+                .insert(transform)
+                .insert(GlobalTransform::default())
+                .id()
+        } else {
+            world
+                .spawn()
+                .insert_bundle(AnimatorBundle {
+                    animator,
+                    transform,
+                    ..Default::default()
+                })
+                .insert(self.damage.clone())
+                .insert(Projectile {
+                    lifetime: self.lifetime,
+                    pierce: self.pierce,
+                })
+                .insert(crate::helper::convex_hull(self.hitbox.clone()))
+                .insert(ZSort)
+                .id()
+        }
+    }
+}
+
 pub struct AttackLoader;
 
 crate::ron_loader!(AttackLoader, "atk" => Attack);
@@ -235,12 +629,16 @@ impl Plugin for AttackPlugin {
         app_builder.add_asset::<Attack>();
         app_builder.add_asset_loader(AttackLoader);
         app_builder.register_network_spawnable::<AttackHitSpawner>();
+        app_builder.register_network_spawnable::<ProjectileSpawner>();
 
         app_builder.add_system(attack_hit_despawn_system.system());
 
         if is_server {
+            app_builder.add_event::<AttackStartEvent>();
+            app_builder.add_event::<HitEvent>();
             app_builder.add_system(attack_server_system.system());
             app_builder.add_system(attack_hit_server_system.system());
+            app_builder.add_system(projectile_server_system.system());
         }
     }
 }
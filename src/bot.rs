@@ -0,0 +1,411 @@
+//! Server side bots, controlled by a behaviour tree.
+//!
+//! Bots send [`PlayerInputEvent`]s as if they were a client, so they're
+//! limited by the same rules as human players.
+
+use crate::attack::*;
+use crate::frame::*;
+use crate::networking::*;
+use crate::player::*;
+use bevy::prelude::*;
+use std::collections::{HashMap, VecDeque};
+
+#[derive(Debug, Hash, PartialEq, Eq, Clone, StageLabel)]
+pub enum BotStage {
+    /// Runs after the network events are received, so the bot input isn't cleared.
+    Think,
+}
+
+/// A node in a bot behaviour tree.
+///
+/// Conditions succeed or fail, actions set the input and always succeed.
+#[derive(Serialize, Deserialize)]
+pub enum Behaviour {
+    /// Runs the children in order until one succeeds.
+    Selector(Vec<Behaviour>),
+    /// Runs the children in order until one fails.
+    Sequence(Vec<Behaviour>),
+    /// Succeeds with a probability, rerolled every `reaction_delay` frames.
+    /// Every chance node in the tree rolls on its own.
+    Chance(f32),
+    /// The opponent is attacking and the attack can reach the bot.
+    OpponentAttacking,
+    /// The opponent is within reach of the bot's light attack.
+    InRange,
+    /// The bot is free to attack and has the stamina to do so.
+    CanAttack,
+    /// The bot's health is below a fraction of the maximum.
+    HealthBelow(f32),
+    /// The bot's stamina is below a fraction of the maximum.
+    StaminaBelow(f32),
+    Approach,
+    Retreat,
+    /// Circles around the opponent.
+    Strafe,
+    Block,
+    Attack,
+    Wait,
+}
+
+/// A bot difficulty, with the behaviour tree the bot follows.
+#[derive(Serialize, Deserialize, TypeUuid)]
+#[serde(deny_unknown_fields)]
+#[uuid = "9b3e5f71-2c8d-4a06-b4e9-7d1f0a6c3e28"]
+pub struct BotDifficulty {
+    pub name: String,
+    /// The number of frames it takes the bot to see what the opponent does.
+    pub reaction_delay: u32,
+    /// The largest error in radians the bot aims with, rerolled every attack.
+    pub aim_error: f32,
+    pub behaviour: Behaviour,
+}
+
+/// What the bot saw of its opponent on a single frame.
+struct Observation {
+    position: Vec2,
+    collider_radius: f32,
+    /// The reach of the opponent's current attack.
+    attack_reach: Option<f32>,
+}
+
+/// The input a bot decided on for a single frame.
+struct BotInput {
+    movement: Vec2,
+    block: bool,
+    attack: bool,
+}
+
+/// Everything a behaviour tree can look at.
+struct BotContext<'a> {
+    player: &'a Player,
+    frame: &'a Frame,
+    attack: &'a Attack,
+    position: Vec2,
+    observation: &'a Observation,
+    /// The roll of every [`Behaviour::Chance`] in the tree, in depth first order.
+    rolls: &'a [f32],
+}
+
+impl Behaviour {
+    /// The number of [`Behaviour::Chance`] nodes in the tree.
+    fn chances(&self) -> usize {
+        match self {
+            Behaviour::Selector(children) | Behaviour::Sequence(children) => {
+                children.iter().map(|child| child.chances()).sum()
+            }
+            Behaviour::Chance(_) => 1,
+            _ => 0,
+        }
+    }
+
+    /// Evaluates the behaviour, returns true if it succeeded.
+    ///
+    /// `first_roll` is the index in [`BotContext::rolls`] of the first chance node in the tree.
+    fn evaluate(&self, context: &BotContext, input: &mut BotInput, first_roll: usize) -> bool {
+        let offset = context.observation.position - context.position;
+        let distance = offset.length();
+        let direction = if distance > 0.0 {
+            offset / distance
+        } else {
+            Vec2::ZERO
+        };
+
+        match self {
+            // children that aren't evaluated still skip their rolls,
+            // so every chance node keeps its roll
+            Behaviour::Selector(children) => {
+                let mut roll = first_roll;
+
+                children.iter().any(|child| {
+                    let success = child.evaluate(context, input, roll);
+                    roll += child.chances();
+                    success
+                })
+            }
+            Behaviour::Sequence(children) => {
+                let mut roll = first_roll;
+
+                children.iter().all(|child| {
+                    let success = child.evaluate(context, input, roll);
+                    roll += child.chances();
+                    success
+                })
+            }
+            Behaviour::Chance(chance) => context.rolls[first_roll] < *chance,
+            Behaviour::OpponentAttacking => {
+                context.observation.attack_reach.map_or(false, |reach| {
+                    distance <= reach + context.frame.collider_radius
+                })
+            }
+            Behaviour::InRange => {
+                distance <= context.attack.reach() + context.observation.collider_radius
+            }
+            Behaviour::CanAttack => {
+                !context.player.attacking
+                    && context.player.stun.is_none()
+                    && context.player.stamina >= context.attack.stamina_cost
+            }
+            Behaviour::HealthBelow(fraction) => {
+                context.player.health < context.frame.max_health * fraction
+            }
+            Behaviour::StaminaBelow(fraction) => {
+                context.player.stamina < context.frame.max_stamina * fraction
+            }
+            Behaviour::Approach => {
+                input.movement = direction;
+                true
+            }
+            Behaviour::Retreat => {
+                input.movement = -direction;
+                true
+            }
+            Behaviour::Strafe => {
+                input.movement = direction.perp();
+                true
+            }
+            Behaviour::Block => {
+                input.block = true;
+                true
+            }
+            Behaviour::Attack => {
+                input.attack = true;
+                true
+            }
+            Behaviour::Wait => true,
+        }
+    }
+}
+
+pub struct Bot {
+    pub difficulty: String,
+    observations: VecDeque<Observation>,
+    rng: u32,
+    rolls: Vec<f32>,
+    /// The aim error of the current attack.
+    aim_error: f32,
+    frames_since_roll: u32,
+}
+
+impl Bot {
+    /// Creates a bot using the [`BotDifficulty`] at the given path,
+    /// bots with the same seed make the same decisions.
+    pub fn new(seed: u32, difficulty: String) -> Self {
+        Self {
+            difficulty,
+            observations: VecDeque::new(),
+            // xorshift can't be seeded with zero
+            rng: seed.wrapping_mul(2654435761) | 1,
+            rolls: Vec::new(),
+            aim_error: 0.0,
+            frames_since_roll: 0,
+        }
+    }
+
+    /// Returns a pseudo random number between 0 and 1.
+    fn random(&mut self) -> f32 {
+        self.rng ^= self.rng << 13;
+        self.rng ^= self.rng >> 17;
+        self.rng ^= self.rng << 5;
+
+        self.rng as f32 / u32::MAX as f32
+    }
+}
+
+/// The bots on the server, by the actor they play as.
+pub struct Bots {
+    pub bots: HashMap<ActorId, Bot>,
+}
+
+impl Bots {
+    pub fn new() -> Self {
+        Self {
+            bots: HashMap::new(),
+        }
+    }
+}
+
+/// Reserves an [`ActorId`] for a new bot, the player still has to be spawned.
+pub fn add_bot(world: &mut World, difficulty: String) -> ActorId {
+    let actor_id = world
+        .get_resource_mut::<NetworkResource>()
+        .unwrap()
+        .reserve_id();
+
+    world
+        .get_resource_mut::<Bots>()
+        .unwrap()
+        .bots
+        .insert(actor_id, Bot::new(actor_id.0, difficulty));
+
+    actor_id
+}
+
+pub fn bot_system(
+    frames: Res<Assets<Frame>>,
+    attacks: Res<Assets<Attack>>,
+    difficulties: Res<Assets<BotDifficulty>>,
+    mut bots: ResMut<Bots>,
+    mut events: ResMut<NetworkEvents<PlayerInputEvent>>,
+    query: Query<(&NetworkEntity, &Player, &Transform, &AttackController)>,
+) {
+    for (network_entity, player, transform, _) in query.iter() {
+        let bot = match bots.bots.get_mut(&player.actor_id) {
+            Some(bot) => bot,
+            None => continue,
+        };
+
+        if player.dead {
+            continue;
+        }
+
+        let difficulty = difficulties.get(bot.difficulty.as_str()).unwrap();
+        let frame = frames.get(&player.frame).unwrap();
+        let attack = attacks.get(frame.light_attack.as_str()).unwrap();
+        let position = transform.translation.truncate();
+
+        // the closest living opponent, on another team
+        let opponent = query
+            .iter()
+            .filter(|(_, other, _, _)| other.team != player.team && !other.dead)
+            .min_by(|(_, _, a, _), (_, _, b, _)| {
+                let a = a.translation.truncate().distance(position);
+                let b = b.translation.truncate().distance(position);
+
+                a.partial_cmp(&b).unwrap()
+            });
+
+        let (_, opponent, opponent_transform, opponent_attack_controller) = match opponent {
+            Some(opponent) => opponent,
+            None => {
+                bot.observations.clear();
+
+                if player.movement_vector != Vec2::ZERO || player.block_held {
+                    events.push(
+                        player.actor_id,
+                        PlayerInputEvent::SetMovement(*network_entity, Vec2::ZERO),
+                    );
+                    events.push(
+                        player.actor_id,
+                        PlayerInputEvent::SetBlock(*network_entity, false),
+                    );
+                }
+
+                continue;
+            }
+        };
+
+        let opponent_frame = frames.get(&opponent.frame).unwrap();
+
+        bot.observations.push_back(Observation {
+            position: opponent_transform.translation.truncate(),
+            collider_radius: opponent_frame.collider_radius,
+            attack_reach: opponent_attack_controller
+                .attack
+                .as_ref()
+                .and_then(|attack| attacks.get(attack))
+                .map(|attack| attack.reach()),
+        });
+
+        // the bot reacts to what happened `reaction_delay` frames ago
+        while bot.observations.len() > difficulty.reaction_delay as usize + 1 {
+            bot.observations.pop_front();
+        }
+
+        bot.frames_since_roll += 1;
+
+        let chances = difficulty.behaviour.chances();
+
+        if bot.frames_since_roll > difficulty.reaction_delay || bot.rolls.len() != chances {
+            bot.frames_since_roll = 0;
+            let rolls = (0..chances).map(|_| bot.random()).collect();
+            bot.rolls = rolls;
+        }
+
+        let observation = bot.observations.front().unwrap();
+
+        let context = BotContext {
+            player,
+            frame,
+            attack,
+            position,
+            observation,
+            rolls: &bot.rolls,
+        };
+
+        let mut input = BotInput {
+            movement: Vec2::ZERO,
+            block: false,
+            attack: false,
+        };
+
+        difficulty.behaviour.evaluate(&context, &mut input, 0);
+
+        let offset = observation.position - position;
+
+        if input.movement != player.movement_vector {
+            events.push(
+                player.actor_id,
+                PlayerInputEvent::SetMovement(*network_entity, input.movement),
+            );
+        }
+
+        if input.block != player.block_held {
+            events.push(
+                player.actor_id,
+                PlayerInputEvent::SetBlock(*network_entity, input.block),
+            );
+        }
+
+        let attack_now = input.attack && !player.attacking && player.stun.is_none();
+
+        if attack_now {
+            bot.aim_error = (bot.random() * 2.0 - 1.0) * difficulty.aim_error;
+        }
+
+        // the aim direction points away from the target
+        if offset.length() > 0.0 {
+            let aim = Quat::from_rotation_z(bot.aim_error) * (-offset.normalize()).extend(0.0);
+
+            events.push(
+                player.actor_id,
+                PlayerInputEvent::SetAimDirection(*network_entity, aim.truncate()),
+            );
+        }
+
+        if attack_now {
+            events.push(
+                player.actor_id,
+                PlayerInputEvent::Attack(*network_entity, AttackType::LightAttack),
+            );
+        }
+    }
+}
+
+pub struct BotDifficultyLoader;
+
+crate::ron_loader!(BotDifficultyLoader, "bot" => BotDifficulty);
+
+pub struct BotPlugin;
+
+impl Plugin for BotPlugin {
+    fn build(&self, app_builder: &mut AppBuilder) {
+        let is_server = app_builder
+            .world()
+            .get_resource::<NetworkSettings>()
+            .unwrap()
+            .is_server;
+
+        app_builder.add_asset::<BotDifficulty>();
+        app_builder.add_asset_loader(BotDifficultyLoader);
+
+        if is_server {
+            app_builder.insert_resource(Bots::new());
+            app_builder.add_stage_after(
+                bevy::app::CoreStage::PreUpdate,
+                BotStage::Think,
+                SystemStage::parallel(),
+            );
+            app_builder.add_system_to_stage(BotStage::Think, bot_system.system());
+        }
+    }
+}
@@ -1,5 +1,8 @@
 use bevy::prelude::*;
 
+/// Half the height of the world the camera shows, in world units.
+pub const CAMERA_SCALE: f32 = 324.0;
+
 pub struct MainCamera;
 
 #[derive(Default)]
@@ -19,7 +22,7 @@ pub fn mouse_system(
         let window = windows.get(event.id).unwrap();
         let size = Vec2::new(window.width() as f32, window.height() as f32);
 
-        let p = (event.position - size / 2.0) / size.y * 324.0 * 2.0;
+        let p = (event.position - size / 2.0) / size.y * CAMERA_SCALE * 2.0;
 
         let world_position = transform
             .compute_matrix()
@@ -1,22 +1,46 @@
 use crate::animation::*;
+use crate::animation_state::*;
 use crate::attack::*;
+use crate::bot::*;
 use crate::camera::*;
+use crate::config::*;
+use crate::debug_render::*;
+use crate::dungeon::*;
+use crate::enemy::*;
 use crate::frame::*;
+use crate::game_settings::*;
+use crate::game_state::*;
 use crate::input::*;
+use crate::item::*;
+use crate::map::*;
+use crate::match_log::*;
 use crate::networking::*;
 use crate::player::*;
 use crate::progress_bar::*;
+use crate::round::*;
+use crate::status_effect::*;
+use crate::training::*;
 use crate::transform::*;
 use bevy::prelude::*;
 
 /// When connecting to the server, the client send a [´ClientGreeting´].
-#[derive(Serialize, Deserialize, TypeUuid)]
+#[derive(Default, Serialize, Deserialize, TypeUuid)]
 #[uuid = "9968f81b-59da-4292-8015-d6d4bbccb5c7"]
-pub struct ClientGreeting {}
+pub struct ClientGreeting {
+    pub name: String,
+    /// The frame the player wants to play as, the server decides if they can.
+    pub frame: String,
+    /// The match to join, see [`ClientConfig::match_code`].
+    pub match_code: String,
+}
 
 /// Runs the client.
-pub fn run(ip: String) {
-    let greeting = ClientGreeting {};
+pub fn run(config: ClientConfig) {
+    let greeting = ClientGreeting {
+        name: config.name,
+        frame: config.frame,
+        match_code: config.match_code,
+    };
     let payload = NetworkPayload::new(&greeting, ClientGreeting::TYPE_UUID);
 
     App::build()
@@ -26,16 +50,30 @@ pub fn run(ip: String) {
         .insert_resource(Mouse::default())
         // plugins
         .add_plugins(DefaultPlugins)
-        .add_plugin(NetworkPlugin::client(ip, payload))
+        .add_plugin(NetworkPlugin::client(config.address, payload))
+        .add_plugin(GameSettingsPlugin)
         .add_plugin(PlayerPlugin)
         .add_plugin(InputPlugin)
         .add_plugin(FramePlugin)
         .add_plugin(ProgressBarPlugin)
         .add_plugin(AnimationPlugin)
+        .add_plugin(AnimationStatePlugin)
         .add_plugin(AttackPlugin)
+        .add_plugin(RoundPlugin)
+        .add_plugin(StatusEffectPlugin)
+        .add_plugin(BotPlugin)
+        .add_plugin(TrainingPlugin)
+        .add_plugin(DebugRenderPlugin)
+        .add_plugin(DungeonPlugin)
+        .add_plugin(EnemyPlugin)
+        .add_plugin(ItemPlugin)
+        .add_plugin(MapPlugin)
+        .add_plugin(MatchLogPlugin)
         // network events
         .register_network_event::<TransformEvent>()
         // network spawnables
+        // state
+        .add_state(GameState::Connection)
         // systems
         .add_system(mouse_system.system())
         .add_system(transform_z_sort_system.system())
@@ -56,7 +94,7 @@ fn setup_system(mut commands: Commands, asset_server: Res<AssetServer>) {
 
     camera_bundle.orthographic_projection.scaling_mode =
         bevy::render::camera::ScalingMode::FixedVertical;
-    camera_bundle.orthographic_projection.scale = 324.0;
+    camera_bundle.orthographic_projection.scale = CAMERA_SCALE;
 
     commands
         .spawn()
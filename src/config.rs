@@ -0,0 +1,131 @@
+//! Configuration files for the server and client, loaded at startup.
+//!
+//! Every field has a default, so a file only needs the fields that differ from
+//! it, and a missing file uses the defaults. Command line options override the files.
+
+use crate::game_settings::*;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+
+/// The address clients connect to and servers listen on, if not configured.
+pub const DEFAULT_ADDRESS: &str = "framefactorserver.ddns.net:35566";
+
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ServerConfig {
+    /// The address the server listens on.
+    pub address: String,
+    /// The number of matches that can be played at the same time.
+    pub max_matches: usize,
+    /// The number of players the game starts with, the game mode decides if not set.
+    pub max_players: Option<usize>,
+    pub mode: GameMode,
+    /// The match is best of `rounds`.
+    pub rounds: u32,
+    pub friendly_fire: bool,
+    /// The path of the map duels are fought in.
+    pub map: String,
+    /// The frames players can pick, players picking any other frame get the first one.
+    pub frames: Vec<String>,
+    /// One of "error", "warn", "info", "debug" or "trace".
+    pub log_level: String,
+    /// Extra log filters, like "wgpu=error,heron=warn".
+    pub log_filter: String,
+}
+
+impl Default for ServerConfig {
+    fn default() -> Self {
+        Self {
+            address: String::from(DEFAULT_ADDRESS),
+            max_matches: 16,
+            max_players: None,
+            mode: GameMode::OneVersusOne,
+            rounds: 3,
+            friendly_fire: false,
+            map: String::from(DEFAULT_MAP),
+            frames: vec![
+                String::from("frames/katana_one/frame.fme"),
+                String::from("frames/bow_man/frame.fme"),
+            ],
+            log_level: String::from("info"),
+            log_filter: String::from("wgpu=error"),
+        }
+    }
+}
+
+impl ServerConfig {
+    pub fn max_players(&self) -> usize {
+        self.max_players.unwrap_or_else(|| self.mode.players())
+    }
+
+    /// The frame a player plays as, if they picked `frame`.
+    pub fn frame(&self, frame: &str) -> String {
+        match self.frames.iter().find(|allowed| *allowed == frame) {
+            Some(frame) => frame.clone(),
+            None => self.frames[0].clone(),
+        }
+    }
+
+    /// Checks the values that can't be checked while parsing.
+    pub fn validate(&self) -> Result<(), String> {
+        if self.frames.is_empty() {
+            return Err(String::from("at least one frame has to be allowed"));
+        }
+
+        if self.max_matches == 0 {
+            return Err(String::from("at least one match has to be allowed"));
+        }
+
+        if self.max_players() < 2 {
+            return Err(String::from("at least two players are needed"));
+        }
+
+        self.log_level
+            .parse::<bevy::log::Level>()
+            .map_err(|_| format!("unknown log level '{}'", self.log_level))?;
+
+        Ok(())
+    }
+
+    pub fn log_settings(&self) -> bevy::log::LogSettings {
+        bevy::log::LogSettings {
+            filter: self.log_filter.clone(),
+            // checked by validate
+            level: self.log_level.parse().unwrap(),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ClientConfig {
+    /// The address of the server.
+    pub address: String,
+    /// The name other players see.
+    pub name: String,
+    /// The frame to play as, if the server allows it.
+    pub frame: String,
+    /// Players with the same code play in the same match, an empty code joins any open match.
+    pub match_code: String,
+}
+
+impl Default for ClientConfig {
+    fn default() -> Self {
+        Self {
+            address: String::from(DEFAULT_ADDRESS),
+            name: String::from("Player"),
+            frame: String::from("frames/katana_one/frame.fme"),
+            match_code: String::new(),
+        }
+    }
+}
+
+/// Loads a configuration file, using the defaults if it doesn't exist.
+pub fn load_config<T: DeserializeOwned + Default>(path: &str) -> Result<T, String> {
+    let bytes = match std::fs::read(path) {
+        Ok(bytes) => bytes,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(T::default()),
+        Err(e) => return Err(format!("failed to read '{}': {}", path, e)),
+    };
+
+    crate::helper::parse_ron(&bytes).map_err(|e| format!("'{}': {}", path, e))
+}
@@ -0,0 +1,299 @@
+//! The admin console of the server, reading commands from stdin.
+//!
+//! Commands run at the start of a frame with access to the whole [`World`]
+//! of the selected match, type `help` for a list of them.
+
+use crate::asset_check::*;
+use crate::game_state::*;
+use crate::networking::*;
+use crate::player::*;
+use crate::round::*;
+use crate::server::{MatchClients, Players};
+use bevy::prelude::*;
+use std::sync::{mpsc::Receiver, Mutex};
+
+pub const HELP: &str = "\
+help                 shows this list
+matches              lists the running matches
+select <code>        sends the following commands to the match with this code
+list                 lists the connected actors and players
+kick <id>            disconnects an actor and despawns their player
+mode <mode>          changes the game mode of new matches, 1v1, 2v2, ffa or koth
+state <state>        forces a game state, like dungeon, duel or ended
+restart              restarts the current round
+health <id> <value>  sets the health of an actor's player
+reload               checks and reloads the assets from disk
+netstats             shows the data sent to and received from every connection";
+
+/// The lines sent to this match, stdin is read by the [`Lobby`](crate::lobby::Lobby).
+pub struct Console {
+    lines: Mutex<Receiver<String>>,
+}
+
+impl Console {
+    pub fn new(lines: Receiver<String>) -> Self {
+        Self {
+            lines: Mutex::new(lines),
+        }
+    }
+
+    fn lines(&self) -> Vec<String> {
+        self.lines.lock().unwrap().try_iter().collect()
+    }
+}
+
+pub fn console_system(world: &mut World) {
+    let lines = world.get_resource::<Console>().unwrap().lines();
+
+    for line in lines {
+        let args = line.split_whitespace().collect::<Vec<_>>();
+
+        let result = match args.as_slice() {
+            [] => Ok(()),
+            ["help"] => {
+                println!("{}", HELP);
+                Ok(())
+            }
+            ["list"] => list(world),
+            ["kick", id] => parse_actor(id).and_then(|id| kick(world, id)),
+            ["state", state] => parse_state(state).and_then(|state| set_state(world, state)),
+            ["restart"] => restart(world),
+            ["health", id, health] => parse_actor(id).and_then(|id| {
+                let health = health
+                    .parse::<f32>()
+                    .map_err(|_| format!("'{}' isn't a number", health))?;
+
+                set_health(world, id, health)
+            }),
+            ["reload"] => reload(world),
+            ["netstats"] => netstats(world),
+            _ => Err(format!(
+                "unknown command '{}', type 'help' for a list of commands",
+                line.trim()
+            )),
+        };
+
+        if let Err(e) = result {
+            println!("{}", e);
+        }
+    }
+}
+
+fn parse_actor(id: &str) -> Result<ActorId, String> {
+    id.parse()
+        .map(ActorId)
+        .map_err(|_| format!("'{}' isn't an actor id", id))
+}
+
+fn parse_state(state: &str) -> Result<GameState, String> {
+    match state.to_lowercase().as_str() {
+        "connection" => Ok(GameState::Connection),
+        "predungeon" => Ok(GameState::PreDungeon),
+        "dungeon" => Ok(GameState::Dungeon),
+        "preduel" => Ok(GameState::PreDuel),
+        "duel" => Ok(GameState::Duel),
+        "ended" => Ok(GameState::Ended),
+        "training" => Ok(GameState::Training),
+        _ => Err(format!("unknown game state '{}'", state)),
+    }
+}
+
+fn list(world: &mut World) -> Result<(), String> {
+    let players = world.get_resource::<Players>().unwrap();
+    let net = world.get_resource::<NetworkResource>().unwrap();
+    let stats = world.get_resource::<NetworkStats>().unwrap();
+
+    let mut lines = Vec::new();
+
+    for id in &players.players {
+        let rtt = match stats.rtt.get(id) {
+            Some(rtt) => format!("{:.0}ms", rtt * 1000.0),
+            // bots and the dummy don't have a connection
+            None if !net.connections.contains_key(id) => String::from("local"),
+            None => String::from("-"),
+        };
+
+        lines.push((*id, players.name(id), rtt));
+    }
+
+    let mut query = world.query::<&Player>();
+
+    println!("{:<6}{:<20}{:<8}{:<8}{}", "id", "name", "rtt", "team", "health");
+
+    for (id, name, rtt) in lines {
+        let player = query.iter(world).find(|player| player.actor_id == id);
+
+        let (team, health) = match player {
+            Some(player) => (
+                format!("{}", player.team.0),
+                format!("{:.1}{}", player.health, if player.dead { " dead" } else { "" }),
+            ),
+            None => (String::from("-"), String::from("not spawned")),
+        };
+
+        println!("{:<6}{:<20}{:<8}{:<8}{}", id.0, name, rtt, team, health);
+    }
+
+    Ok(())
+}
+
+fn kick(world: &mut World, id: ActorId) -> Result<(), String> {
+    let mut net = world.get_resource_mut::<NetworkResource>().unwrap();
+
+    if !net.connections.contains_key(&id) {
+        return Err(format!("'{}' isn't connected", id.0));
+    }
+
+    // dropping the connection closes it
+    net.remove_connection(&id);
+
+    world.get_resource_mut::<NetworkStats>().unwrap().rtt.remove(&id);
+
+    let mut players = world.get_resource_mut::<Players>().unwrap();
+    let name = players.name(&id);
+    let removed = players.remove(&id);
+
+    // before the game starts the slot can be taken by another client
+    let state = world.get_resource::<State<GameState>>().unwrap();
+
+    if removed && *state.current() == GameState::Connection {
+        world.get_resource::<MatchClients>().unwrap().free();
+    }
+
+    let mut query = world.query::<(&NetworkEntity, &Player)>();
+
+    let network_entity = query
+        .iter(world)
+        .find(|(_, player)| player.actor_id == id)
+        .map(|(network_entity, _)| *network_entity);
+
+    if let Some(network_entity) = network_entity {
+        world
+            .get_resource::<NetworkSpawner>()
+            .unwrap()
+            .despawn(network_entity);
+    }
+
+    info!("Kicked '{}' ({:?})", name, id);
+
+    Ok(())
+}
+
+fn set_state(world: &mut World, state: GameState) -> Result<(), String> {
+    world
+        .get_resource_mut::<State<GameState>>()
+        .unwrap()
+        .overwrite_set(state.clone())
+        .map_err(|e| format!("can't change the state to {:?}: {:?}", state, e))?;
+
+    info!("Forced the state to {:?}", state);
+
+    Ok(())
+}
+
+fn restart(world: &mut World) -> Result<(), String> {
+    if *world.get_resource::<State<GameState>>().unwrap().current() != GameState::Duel {
+        return Err(String::from("rounds can only be restarted in a duel"));
+    }
+
+    let mut rounds = world.get_resource_mut::<Rounds>().unwrap();
+
+    // the round system starts the next round, with the same number
+    rounds.round -= 1;
+    rounds.reset_timer = Some(0);
+
+    info!("Restarting round {}", rounds.round + 1);
+
+    Ok(())
+}
+
+fn set_health(world: &mut World, id: ActorId, health: f32) -> Result<(), String> {
+    let mut query = world.query::<&mut Player>();
+
+    let mut player = query
+        .iter_mut(world)
+        .find(|player| player.actor_id == id)
+        .ok_or_else(|| format!("'{}' doesn't have a player", id.0))?;
+
+    // the death system kills the player if the health is 0
+    player.health = health;
+    player.update_health = true;
+
+    Ok(())
+}
+
+fn reload(world: &mut World) -> Result<(), String> {
+    let mut checker = AssetChecker::new("assets");
+
+    checker
+        .check()
+        .map_err(|e| format!("failed to read the assets: {}", e))?;
+
+    if !checker.errors().is_empty() {
+        for error in checker.errors() {
+            println!("{}", error);
+        }
+
+        return Err(format!(
+            "{} errors found, nothing was reloaded",
+            checker.errors().len()
+        ));
+    }
+
+    // replaces the assets behind the path handles, so everything using them sees the change
+    checker.insert_assets(world);
+
+    info!("Reloaded the assets");
+
+    Ok(())
+}
+
+fn netstats(world: &mut World) -> Result<(), String> {
+    let net = world.get_resource::<NetworkResource>().unwrap();
+    let stats = world.get_resource::<NetworkStats>().unwrap();
+    let players = world.get_resource::<Players>().unwrap();
+
+    println!(
+        "{:<6}{:<20}{:<24}{:<8}{:>10}{:>12}{:>10}{:>12}",
+        "id", "name", "address", "rtt", "sent", "sent bytes", "received", "recv bytes"
+    );
+
+    let mut connections = net.connections.iter().collect::<Vec<_>>();
+    connections.sort_by_key(|(id, _)| id.0);
+
+    for (id, connection) in connections {
+        let connection_stats = connection.stats();
+
+        let address = connection
+            .peer_addr()
+            .map_or(String::from("-"), |address| address.to_string());
+
+        let rtt = stats
+            .rtt
+            .get(id)
+            .map_or(String::from("-"), |rtt| format!("{:.0}ms", rtt * 1000.0));
+
+        println!(
+            "{:<6}{:<20}{:<24}{:<8}{:>10}{:>12}{:>10}{:>12}",
+            id.0,
+            players.name(id),
+            address,
+            rtt,
+            connection_stats.payloads_sent,
+            connection_stats.bytes_sent,
+            connection_stats.payloads_received,
+            connection_stats.bytes_received,
+        );
+    }
+
+    Ok(())
+}
+
+/// Adds the admin console, only used on servers, which insert the [`Console`] resource.
+pub struct ConsolePlugin;
+
+impl Plugin for ConsolePlugin {
+    fn build(&self, app_builder: &mut AppBuilder) {
+        app_builder.add_system(console_system.exclusive_system());
+    }
+}
@@ -0,0 +1,302 @@
+//! Debug rendering of hitboxes, colliders, aim directions and network entities.
+//!
+//! Toggled on the client with the `debug_render` input, every shape is coloured
+//! by the [`NetworkEntity`] of its owner.
+
+use crate::attack::*;
+use crate::frame::*;
+use crate::input::*;
+use crate::networking::*;
+use crate::player::*;
+use bevy::{
+    prelude::*,
+    render::{mesh::Indices, pipeline::PrimitiveTopology},
+};
+use heron::prelude::*;
+use std::collections::HashSet;
+
+/// The depth debug shapes are drawn at, above everything else.
+const DEBUG_Z: f32 = 10.0;
+const AIM_LENGTH: f32 = 48.0;
+const AIM_WIDTH: f32 = 1.5;
+const CIRCLE_SEGMENTS: usize = 24;
+
+const OWNER_COLORS: [Color; 6] = [
+    Color::rgb(1.0, 0.25, 0.25),
+    Color::rgb(0.25, 0.5, 1.0),
+    Color::rgb(0.25, 1.0, 0.25),
+    Color::rgb(1.0, 0.85, 0.2),
+    Color::rgb(0.85, 0.3, 1.0),
+    Color::rgb(0.2, 0.9, 0.9),
+];
+
+pub struct DebugRender {
+    pub enabled: bool,
+    /// The entities debug shapes have been spawned for.
+    targets: HashSet<Entity>,
+}
+
+impl DebugRender {
+    pub fn new() -> Self {
+        Self {
+            enabled: false,
+            targets: HashSet::new(),
+        }
+    }
+}
+
+enum DebugShapeKind {
+    /// A collider or hitbox, rotated with the target.
+    Body,
+    /// The direction a player attacks in.
+    Aim,
+    Label,
+}
+
+/// A debug shape, following the transform of `target`.
+pub struct DebugShape {
+    target: Entity,
+    kind: DebugShapeKind,
+}
+
+fn owner_color(owner: &NetworkEntity, alpha: f32) -> Color {
+    let mut color = OWNER_COLORS[owner.id() as usize % OWNER_COLORS.len()];
+    color.set_a(alpha);
+    color
+}
+
+/// Creates a flat mesh of a convex polygon, the points can be in any order.
+pub fn polygon_mesh(points: &[Vec2]) -> Mesh {
+    let center = points.iter().fold(Vec2::ZERO, |sum, point| sum + *point) / points.len() as f32;
+
+    // counter clockwise, so the triangles face the camera
+    let mut points = points.to_vec();
+    points.sort_by(|a, b| {
+        let a = (*a - center).y.atan2((*a - center).x);
+        let b = (*b - center).y.atan2((*b - center).x);
+
+        a.partial_cmp(&b).unwrap()
+    });
+
+    let positions = points
+        .iter()
+        .map(|point| [point.x, point.y, 0.0])
+        .collect::<Vec<_>>();
+    let normals = vec![[0.0, 0.0, 1.0]; points.len()];
+    let uvs = vec![[0.0, 0.0]; points.len()];
+
+    // a triangle fan, since the polygon is convex
+    let indices = (1..points.len().saturating_sub(1) as u32)
+        .flat_map(|i| vec![0, i, i + 1])
+        .collect::<Vec<_>>();
+
+    let mut mesh = Mesh::new(PrimitiveTopology::TriangleList);
+    mesh.set_attribute(Mesh::ATTRIBUTE_POSITION, positions);
+    mesh.set_attribute(Mesh::ATTRIBUTE_NORMAL, normals);
+    mesh.set_attribute(Mesh::ATTRIBUTE_UV_0, uvs);
+    mesh.set_indices(Some(Indices::U32(indices)));
+
+    mesh
+}
+
+fn circle_points(radius: f32) -> Vec<Vec2> {
+    (0..CIRCLE_SEGMENTS)
+        .map(|i| {
+            let angle = i as f32 / CIRCLE_SEGMENTS as f32 * std::f32::consts::TAU;
+            Vec2::new(angle.cos(), angle.sin()) * radius
+        })
+        .collect()
+}
+
+/// The assets and commands needed to spawn debug shapes.
+struct ShapeSpawner<'a, 'b> {
+    commands: &'a mut Commands<'b>,
+    meshes: &'a mut Assets<Mesh>,
+    materials: &'a mut Assets<ColorMaterial>,
+    font: Handle<Font>,
+}
+
+impl<'a, 'b> ShapeSpawner<'a, 'b> {
+    fn shape(&mut self, target: Entity, kind: DebugShapeKind, points: &[Vec2], color: Color) {
+        self.commands
+            .spawn_bundle(SpriteBundle {
+                sprite: Sprite::new(Vec2::ONE),
+                mesh: self.meshes.add(polygon_mesh(points)),
+                material: self.materials.add(color.into()),
+                transform: Transform::from_translation(Vec3::new(0.0, 0.0, DEBUG_Z)),
+                ..Default::default()
+            })
+            .insert(DebugShape { target, kind });
+    }
+
+    fn label(&mut self, target: Entity, network_entity: &NetworkEntity) {
+        self.commands
+            .spawn_bundle(Text2dBundle {
+                text: Text::with_section(
+                    network_entity.id().to_string(),
+                    TextStyle {
+                        font: self.font.clone(),
+                        font_size: 12.0,
+                        color: Color::WHITE,
+                    },
+                    TextAlignment {
+                        vertical: VerticalAlign::Center,
+                        horizontal: HorizontalAlign::Center,
+                    },
+                ),
+                transform: Transform::from_translation(Vec3::new(0.0, 0.0, DEBUG_Z + 1.0)),
+                ..Default::default()
+            })
+            .insert(DebugShape {
+                target,
+                kind: DebugShapeKind::Label,
+            });
+    }
+}
+
+pub fn debug_render_toggle_system(
+    mut commands: Commands,
+    input_handle: Res<Handle<InputSettings>>,
+    input_settings: Res<Assets<InputSettings>>,
+    keyboard_input: Res<Input<KeyCode>>,
+    mouse_input: Res<Input<MouseButton>>,
+    mut debug_render: ResMut<DebugRender>,
+    query: Query<Entity, With<DebugShape>>,
+) {
+    let input = match input_settings.get(&*input_handle) {
+        Some(input) => input,
+        None => return,
+    };
+
+    let input_ctx = InputCtx {
+        keyboard: &*keyboard_input,
+        mouse: &*mouse_input,
+    };
+
+    if !input.debug_render.just_pressed(&input_ctx) {
+        return;
+    }
+
+    debug_render.enabled = !debug_render.enabled;
+
+    if !debug_render.enabled {
+        debug_render.targets.clear();
+
+        for entity in query.iter() {
+            commands.entity(entity).despawn();
+        }
+    }
+}
+
+/// Spawns debug shapes for players and hitboxes that don't have them yet.
+pub fn debug_render_spawn_system(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    frames: Res<Assets<Frame>>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<ColorMaterial>>,
+    mut debug_render: ResMut<DebugRender>,
+    player_query: Query<(Entity, &NetworkEntity, &Player)>,
+    damage_query: Query<(Entity, &NetworkEntity, &Damage, &Body)>,
+) {
+    if !debug_render.enabled {
+        return;
+    }
+
+    let mut spawner = ShapeSpawner {
+        commands: &mut commands,
+        meshes: &mut *meshes,
+        materials: &mut *materials,
+        font: asset_server.load("fonts/DejaVuSansMono.ttf"),
+    };
+
+    for (entity, network_entity, player) in player_query.iter() {
+        if !debug_render.targets.insert(entity) {
+            continue;
+        }
+
+        let frame = frames.get(&player.frame).unwrap();
+
+        spawner.shape(
+            entity,
+            DebugShapeKind::Body,
+            &circle_points(frame.collider_radius),
+            owner_color(network_entity, 0.3),
+        );
+        spawner.shape(
+            entity,
+            DebugShapeKind::Aim,
+            &[
+                Vec2::new(-AIM_WIDTH, 0.0),
+                Vec2::new(AIM_WIDTH, 0.0),
+                Vec2::new(AIM_WIDTH, AIM_LENGTH),
+                Vec2::new(-AIM_WIDTH, AIM_LENGTH),
+            ],
+            owner_color(network_entity, 0.9),
+        );
+        spawner.label(entity, network_entity);
+    }
+
+    for (entity, network_entity, damage, body) in damage_query.iter() {
+        if !debug_render.targets.insert(entity) {
+            continue;
+        }
+
+        let points = match body {
+            Body::ConvexHull { points } => points
+                .iter()
+                .map(|point| point.truncate())
+                .collect::<Vec<_>>(),
+            _ => continue,
+        };
+
+        spawner.shape(
+            entity,
+            DebugShapeKind::Body,
+            &points,
+            owner_color(&damage.source, 0.5),
+        );
+        spawner.label(entity, network_entity);
+    }
+}
+
+/// Moves the debug shapes to their targets, and despawns them with their targets.
+pub fn debug_render_update_system(
+    mut commands: Commands,
+    mut debug_render: ResMut<DebugRender>,
+    target_query: Query<(&GlobalTransform, Option<&Player>)>,
+    mut shape_query: Query<(Entity, &DebugShape, &mut Transform)>,
+) {
+    for (entity, shape, mut transform) in shape_query.iter_mut() {
+        let (target_transform, player) = match target_query.get(shape.target) {
+            Ok(target) => target,
+            Err(_) => {
+                debug_render.targets.remove(&shape.target);
+                commands.entity(entity).despawn();
+                continue;
+            }
+        };
+
+        let z = transform.translation.z;
+        transform.translation = target_transform.translation.truncate().extend(z);
+
+        transform.rotation = match shape.kind {
+            DebugShapeKind::Body => target_transform.rotation,
+            DebugShapeKind::Aim => player.map_or(Quat::IDENTITY, |player| {
+                aim_rotation(player.aim_direction)
+            }),
+            DebugShapeKind::Label => Quat::IDENTITY,
+        };
+    }
+}
+
+pub struct DebugRenderPlugin;
+
+impl Plugin for DebugRenderPlugin {
+    fn build(&self, app_builder: &mut AppBuilder) {
+        app_builder.insert_resource(DebugRender::new());
+        app_builder.add_system(debug_render_toggle_system.system());
+        app_builder.add_system(debug_render_spawn_system.system());
+        app_builder.add_system(debug_render_update_system.system());
+    }
+}
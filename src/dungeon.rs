@@ -0,0 +1,757 @@
+//! Procedurally generated dungeons, played in [`GameState::Dungeon`].
+//!
+//! The server picks a seed and sends it to the clients, which generate the
+//! same layout from it. Only the server spawns wall colliders.
+
+use crate::enemy::*;
+use crate::game_settings::*;
+use crate::game_state::*;
+use crate::item::*;
+use crate::match_log::*;
+use crate::networking::*;
+use crate::player::*;
+use crate::server::TICK_RATE;
+use bevy::prelude::*;
+use heron::prelude::*;
+use std::collections::{HashMap, HashSet, VecDeque};
+
+/// The size of a tile in world units.
+pub const TILE_SIZE: f32 = 16.0;
+/// The depth tiles are drawn at, behind every z sorted entity inside the dungeon.
+const TILE_Z: f32 = -0.099;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Tile {
+    Wall,
+    Floor,
+}
+
+/// A rectangular room, in tiles.
+#[derive(Clone, Copy, Debug)]
+pub struct Room {
+    pub x: i32,
+    pub y: i32,
+    pub width: i32,
+    pub height: i32,
+}
+
+impl Room {
+    pub fn center(&self) -> (i32, i32) {
+        (self.x + self.width / 2, self.y + self.height / 2)
+    }
+
+    /// Returns true if the rooms are closer than `margin` tiles.
+    fn intersects(&self, other: &Room, margin: i32) -> bool {
+        self.x - margin < other.x + other.width
+            && self.x + self.width + margin > other.x
+            && self.y - margin < other.y + other.height
+            && self.y + self.height + margin > other.y
+    }
+}
+
+/// The parameters of the generator, clients have to use the same ones as the server.
+pub struct DungeonParams {
+    pub width: i32,
+    pub height: i32,
+    pub max_rooms: usize,
+    /// The number of times the generator tries to place a room.
+    pub room_attempts: u32,
+    pub min_room_size: i32,
+    pub max_room_size: i32,
+    pub corridor_width: i32,
+    /// The paths of the enemies that can spawn.
+    pub enemies: Vec<String>,
+    /// The number of enemies in every room players don't spawn in.
+    pub enemies_per_room: u32,
+    /// The number of chests, in random rooms players don't spawn in.
+    pub chests: u32,
+    pub chest_loot: Vec<LootDrop>,
+    /// The number of frames the dungeon lasts, if any enemies are left.
+    pub time_limit: u32,
+}
+
+impl Default for DungeonParams {
+    fn default() -> Self {
+        // small enough to fit on screen
+        Self {
+            width: 64,
+            height: 24,
+            max_rooms: 8,
+            room_attempts: 64,
+            min_room_size: 5,
+            max_room_size: 10,
+            corridor_width: 2,
+            enemies: vec![
+                String::from("enemies/skeleton.enm"),
+                String::from("enemies/archer.enm"),
+            ],
+            enemies_per_room: 2,
+            chests: 2,
+            chest_loot: vec![
+                LootDrop {
+                    item: String::from("items/vitality_charm.itm"),
+                    chance: 0.5,
+                },
+                LootDrop {
+                    item: String::from("items/swift_boots.itm"),
+                    chance: 0.3,
+                },
+                LootDrop {
+                    item: String::from("items/heavy_blade.itm"),
+                    chance: 0.2,
+                },
+            ],
+            time_limit: TICK_RATE as u32 * 180,
+        }
+    }
+}
+
+/// A deterministic random number generator, so every machine generates the same dungeon.
+struct DungeonRng {
+    state: u64,
+}
+
+impl DungeonRng {
+    fn new(seed: u64) -> Self {
+        // splitmix64, so similar seeds give different dungeons
+        let mut z = seed.wrapping_add(0x9e3779b97f4a7c15);
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+
+        // xorshift can't be seeded with zero
+        Self {
+            state: (z ^ (z >> 31)) | 1,
+        }
+    }
+
+    fn next(&mut self) -> u64 {
+        self.state ^= self.state << 13;
+        self.state ^= self.state >> 7;
+        self.state ^= self.state << 17;
+        self.state
+    }
+
+    /// Returns a number between `min` and `max`, inclusive.
+    fn range(&mut self, min: i32, max: i32) -> i32 {
+        if max <= min {
+            return min;
+        }
+
+        min + (self.next() % (max - min + 1) as u64) as i32
+    }
+}
+
+/// Returns a seed based on the current time.
+pub fn random_seed() -> u64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap()
+        .as_nanos() as u64
+}
+
+pub struct DungeonLayout {
+    pub seed: u64,
+    pub width: i32,
+    pub height: i32,
+    tiles: Vec<Tile>,
+    /// The rooms in the order they were placed, each one is connected to the one before it.
+    pub rooms: Vec<Room>,
+}
+
+impl DungeonLayout {
+    pub fn generate(seed: u64, params: &DungeonParams) -> Self {
+        let mut rng = DungeonRng::new(seed);
+
+        let mut layout = Self {
+            seed,
+            width: params.width,
+            height: params.height,
+            tiles: vec![Tile::Wall; (params.width * params.height) as usize],
+            rooms: Vec::new(),
+        };
+
+        for _ in 0..params.room_attempts {
+            if layout.rooms.len() >= params.max_rooms {
+                break;
+            }
+
+            let width = rng.range(params.min_room_size, params.max_room_size);
+            let height = rng.range(params.min_room_size, params.max_room_size);
+
+            // keep a wall around the edge of the map
+            let room = Room {
+                x: rng.range(1, params.width - width - 1),
+                y: rng.range(1, params.height - height - 1),
+                width,
+                height,
+            };
+
+            if layout.rooms.iter().any(|other| room.intersects(other, 1)) {
+                continue;
+            }
+
+            layout.carve(room.x, room.y, room.width, room.height);
+
+            if let Some(previous) = layout.rooms.last().copied() {
+                let horizontal_first = rng.next() % 2 == 0;
+
+                layout.carve_corridor(
+                    previous.center(),
+                    room.center(),
+                    horizontal_first,
+                    params.corridor_width,
+                );
+            }
+
+            layout.rooms.push(room);
+        }
+
+        layout
+    }
+
+    /// Returns the tile at a position, everything outside the map is wall.
+    pub fn tile(&self, x: i32, y: i32) -> Tile {
+        if x < 0 || y < 0 || x >= self.width || y >= self.height {
+            return Tile::Wall;
+        }
+
+        self.tiles[(y * self.width + x) as usize]
+    }
+
+    fn carve(&mut self, x: i32, y: i32, width: i32, height: i32) {
+        for y in y.max(1)..(y + height).min(self.height - 1) {
+            for x in x.max(1)..(x + width).min(self.width - 1) {
+                self.tiles[(y * self.width + x) as usize] = Tile::Floor;
+            }
+        }
+    }
+
+    /// Carves an L shaped corridor between two tiles.
+    fn carve_corridor(
+        &mut self,
+        from: (i32, i32),
+        to: (i32, i32),
+        horizontal_first: bool,
+        width: i32,
+    ) {
+        let corner = if horizontal_first {
+            (to.0, from.1)
+        } else {
+            (from.0, to.1)
+        };
+
+        for &(a, b) in &[(from, corner), (corner, to)] {
+            let x = a.0.min(b.0);
+            let y = a.1.min(b.1);
+
+            self.carve(
+                x,
+                y,
+                (a.0 - b.0).abs() + width,
+                (a.1 - b.1).abs() + width,
+            );
+        }
+    }
+
+    /// Returns the world position of the center of a tile, the map is centered on the origin.
+    pub fn tile_position(&self, x: i32, y: i32) -> Vec2 {
+        Vec2::new(
+            x as f32 - self.width as f32 / 2.0 + 0.5,
+            y as f32 - self.height as f32 / 2.0 + 0.5,
+        ) * TILE_SIZE
+    }
+
+    /// The indices of the rooms in the order players spawn in, alternating between both
+    /// ends of the chain of rooms, so the first two are the furthest apart to walk.
+    fn spawn_order(&self) -> Vec<usize> {
+        let mut rooms = (0..self.rooms.len()).collect::<VecDeque<_>>();
+        let mut order = Vec::new();
+
+        loop {
+            let room = if order.len() % 2 == 0 {
+                rooms.pop_front()
+            } else {
+                rooms.pop_back()
+            };
+
+            match room {
+                Some(room) => order.push(room),
+                None => break,
+            }
+        }
+
+        order
+    }
+
+    /// The spawn points in the center of every room, in the order players spawn in.
+    pub fn spawn_points(&self) -> Vec<Vec2> {
+        self.spawn_order()
+            .into_iter()
+            .map(|room| {
+                let (x, y) = self.rooms[room].center();
+                self.tile_position(x, y)
+            })
+            .collect()
+    }
+
+    /// The rooms none of the players spawn in.
+    fn free_rooms(&self, players: usize) -> Vec<&Room> {
+        let order = self.spawn_order();
+        let taken = &order[..players.min(order.len())];
+
+        self.rooms
+            .iter()
+            .enumerate()
+            .filter(|(room, _)| !taken.contains(room))
+            .map(|(_, room)| room)
+            .collect()
+    }
+
+    /// The enemies to spawn and where, in every room none of the players spawn in.
+    pub fn enemy_spawns(&self, params: &DungeonParams, players: usize) -> Vec<(String, Vec2)> {
+        // a different sequence than the layout, so changing the enemies doesn't change the layout
+        let mut rng = DungeonRng::new(self.seed ^ 0x5bd1e995);
+        let mut spawns = Vec::new();
+
+        if params.enemies.is_empty() {
+            return spawns;
+        }
+
+        for room in self.free_rooms(players) {
+            for _ in 0..params.enemies_per_room {
+                let enemy = &params.enemies[rng.range(0, params.enemies.len() as i32 - 1) as usize];
+
+                // away from the walls of the room
+                let x = rng.range(room.x + 1, room.x + room.width - 2);
+                let y = rng.range(room.y + 1, room.y + room.height - 2);
+
+                spawns.push((enemy.clone(), self.tile_position(x, y)));
+            }
+        }
+
+        spawns
+    }
+
+    /// Where to place the chests, in the center of random rooms players don't spawn in.
+    pub fn chest_spawns(&self, params: &DungeonParams, players: usize) -> Vec<Vec2> {
+        let mut rng = DungeonRng::new(self.seed ^ 0x27d4eb2f);
+        let rooms = self.free_rooms(players);
+
+        if rooms.is_empty() {
+            return Vec::new();
+        }
+
+        (0..params.chests)
+            .map(|_| {
+                let room = rooms[rng.range(0, rooms.len() as i32 - 1) as usize];
+                let (x, y) = room.center();
+
+                self.tile_position(x, y)
+            })
+            .collect()
+    }
+
+    /// The wall tiles bordering a floor tile, these are the only ones that need colliders.
+    pub fn boundary_walls(&self) -> Vec<(i32, i32)> {
+        let mut walls = Vec::new();
+
+        for y in 0..self.height {
+            for x in 0..self.width {
+                if self.tile(x, y) != Tile::Wall {
+                    continue;
+                }
+
+                let borders_floor = (-1..=1)
+                    .flat_map(|dy| (-1..=1).map(move |dx| (dx, dy)))
+                    .any(|(dx, dy)| self.tile(x + dx, y + dy) == Tile::Floor);
+
+                if borders_floor {
+                    walls.push((x, y));
+                }
+            }
+        }
+
+        walls
+    }
+
+    /// Returns true if every floor tile can be reached from every other one.
+    pub fn is_connected(&self) -> bool {
+        let floor_tiles = self.tiles.iter().filter(|tile| **tile == Tile::Floor).count();
+
+        let start = match self.tiles.iter().position(|tile| *tile == Tile::Floor) {
+            Some(index) => (index as i32 % self.width, index as i32 / self.width),
+            None => return true,
+        };
+
+        let mut visited = HashSet::new();
+        let mut queue = VecDeque::new();
+
+        visited.insert(start);
+        queue.push_back(start);
+
+        while let Some((x, y)) = queue.pop_front() {
+            for &(dx, dy) in &[(1, 0), (-1, 0), (0, 1), (0, -1)] {
+                let next = (x + dx, y + dy);
+
+                if self.tile(next.0, next.1) == Tile::Floor && visited.insert(next) {
+                    queue.push_back(next);
+                }
+            }
+        }
+
+        visited.len() == floor_tiles
+    }
+
+    /// Draws the layout with `#` for walls and `.` for floors, top row first.
+    pub fn to_ascii(&self) -> String {
+        let mut output = String::new();
+
+        for y in (0..self.height).rev() {
+            for x in 0..self.width {
+                output.push(match self.tile(x, y) {
+                    Tile::Wall => '#',
+                    Tile::Floor => '.',
+                });
+            }
+
+            output.push('\n');
+        }
+
+        output
+    }
+}
+
+/// Sent to the clients when a dungeon is generated.
+#[derive(Serialize, Deserialize, TypeUuid)]
+#[uuid = "c7d35a1e-8f42-4b6c-a0e9-51b2d8f64c17"]
+pub enum DungeonEvent {
+    Generate { seed: u64 },
+}
+
+/// The current dungeon.
+pub struct Dungeon {
+    pub layout: DungeonLayout,
+    /// The parameters the layout was generated with.
+    pub params: DungeonParams,
+    /// The players that have been moved to a spawn point, with where they spawned before.
+    placed: HashMap<Entity, Vec2>,
+    /// The number of frames since the dungeon was generated.
+    pub ticks: u32,
+}
+
+impl Dungeon {
+    pub fn new(layout: DungeonLayout, params: DungeonParams) -> Self {
+        Self {
+            layout,
+            params,
+            placed: HashMap::new(),
+            ticks: 0,
+        }
+    }
+}
+
+/// Marks the walls and tiles of a dungeon, which are despawned when leaving it.
+pub struct DungeonTile;
+
+pub fn dungeon_start_server_system(
+    mut commands: Commands,
+    game_settings: Res<GameSettings>,
+    enemies: Res<Assets<Enemy>>,
+    mut net: ResMut<NetworkResource>,
+    network_spawner: Res<NetworkSpawner>,
+    event_sender: Res<NetworkEventSender>,
+    mut match_log: ResMut<MatchLog>,
+) {
+    let seed = game_settings.dungeon_seed.unwrap_or_else(random_seed);
+
+    info!("Generating dungeon with seed {}", seed);
+    match_log.write(MatchLogEvent::DungeonGenerated { seed });
+
+    let params = DungeonParams::default();
+    let layout = DungeonLayout::generate(seed, &params);
+    let players = game_settings.mode.players();
+
+    for (enemy, position) in layout.enemy_spawns(&params, players) {
+        spawn_enemy(&enemies, &mut net, &network_spawner, &enemy, position);
+    }
+
+    for position in layout.chest_spawns(&params, players) {
+        network_spawner.spawn(ChestSpawner {
+            loot: params.chest_loot.clone(),
+            position,
+        });
+    }
+
+    for (x, y) in layout.boundary_walls() {
+        commands
+            .spawn()
+            .insert(Transform::from_translation(
+                layout.tile_position(x, y).extend(0.0),
+            ))
+            .insert(GlobalTransform::default())
+            .insert(Body::Cuboid {
+                half_extends: Vec3::new(TILE_SIZE / 2.0, TILE_SIZE / 2.0, 0.0),
+            })
+            .insert(BodyType::Static)
+            .insert(DungeonTile);
+    }
+
+    event_sender
+        .send(&DungeonEvent::Generate { seed })
+        .unwrap();
+
+    commands.insert_resource(Dungeon::new(layout, params));
+}
+
+/// Moves players to the spawn points, they might be spawned after the dungeon is generated,
+/// and ends the dungeon when every enemy is dead and their loot picked up, or the time is up.
+pub fn dungeon_server_system(
+    dungeon: Option<ResMut<Dungeon>>,
+    mut state: ResMut<State<GameState>>,
+    mut query: Query<(Entity, &mut Player, &mut Transform), Without<EnemyController>>,
+    enemy_query: Query<(&Player, &EnemyController), With<EnemyController>>,
+    pickup_query: Query<(), With<ItemPickup>>,
+) {
+    // the dungeon is inserted at the end of the frame it's generated on
+    let mut dungeon = match dungeon {
+        Some(dungeon) => dungeon,
+        None => return,
+    };
+
+    let spawn_points = dungeon.layout.spawn_points();
+
+    for (entity, mut player, mut transform) in query.iter_mut() {
+        if dungeon.placed.contains_key(&entity) {
+            continue;
+        }
+
+        let spawn_point = match spawn_points.get(dungeon.placed.len()) {
+            Some(spawn_point) => *spawn_point,
+            None => spawn_points.first().copied().unwrap_or(Vec2::ZERO),
+        };
+
+        dungeon.placed.insert(entity, player.spawn_position);
+
+        player.spawn_position = spawn_point;
+        transform.translation = spawn_point.extend(0.0);
+    }
+
+    dungeon.ticks += 1;
+
+    // the enemies are spawned a frame after the dungeon is generated
+    let enemies_dead = dungeon.ticks > 1
+        && enemy_query
+            .iter()
+            .all(|(enemy, controller)| enemy.dead && controller.dropped_loot);
+
+    if (enemies_dead && pickup_query.iter().next().is_none())
+        || dungeon.ticks >= dungeon.params.time_limit
+    {
+        info!("Dungeon ended after {} frames", dungeon.ticks);
+
+        state.set(GameState::PreDuel).unwrap();
+    }
+}
+
+/// Moves the players back to where they spawned before the dungeon.
+pub fn dungeon_exit_server_system(
+    dungeon: Res<Dungeon>,
+    mut query: Query<(Entity, &mut Player, &mut Transform)>,
+) {
+    for (entity, mut player, mut transform) in query.iter_mut() {
+        if let Some(spawn_position) = dungeon.placed.get(&entity) {
+            player.spawn_position = *spawn_position;
+            transform.translation = spawn_position.extend(0.0);
+        }
+    }
+}
+
+pub fn dungeon_exit_system(mut commands: Commands, query: Query<Entity, With<DungeonTile>>) {
+    for entity in query.iter() {
+        commands.entity(entity).despawn();
+    }
+}
+
+pub fn dungeon_client_system(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    mut materials: ResMut<Assets<ColorMaterial>>,
+    mut events: ResMut<NetworkEvents<DungeonEvent>>,
+    mut state: ResMut<State<GameState>>,
+) {
+    for (_sender, event) in events.take() {
+        match event {
+            DungeonEvent::Generate { seed } => {
+                info!("Generating dungeon with seed {}", seed);
+
+                let params = DungeonParams::default();
+                let layout = DungeonLayout::generate(seed, &params);
+
+                let floor = materials.add(asset_server.load("dungeon/floor.png").into());
+                let wall = materials.add(asset_server.load("dungeon/wall.png").into());
+
+                for y in 0..layout.height {
+                    for x in 0..layout.width {
+                        let material = match layout.tile(x, y) {
+                            Tile::Wall => wall.clone(),
+                            Tile::Floor => floor.clone(),
+                        };
+
+                        commands
+                            .spawn_bundle(SpriteBundle {
+                                sprite: Sprite::new(Vec2::splat(TILE_SIZE)),
+                                material,
+                                transform: Transform::from_translation(
+                                    layout.tile_position(x, y).extend(TILE_Z),
+                                ),
+                                ..Default::default()
+                            })
+                            .insert(DungeonTile);
+                    }
+                }
+
+                commands.insert_resource(Dungeon::new(layout, params));
+
+                if *state.current() != GameState::Dungeon {
+                    state.overwrite_set(GameState::Dungeon).unwrap();
+                }
+            }
+        }
+    }
+}
+
+/// Generates the dungeon for a seed and prints it, then checks that it's connected.
+///
+/// Returns false if the dungeon is invalid for the players of the game mode.
+pub fn print_dungeon(seed: u64, mode: GameMode) -> bool {
+    let layout = DungeonLayout::generate(seed, &DungeonParams::default());
+
+    print!("{}", layout.to_ascii());
+    println!(
+        "seed: {}, rooms: {}, enemies: {}",
+        seed,
+        layout.rooms.len(),
+        layout
+            .enemy_spawns(&DungeonParams::default(), mode.players())
+            .len()
+    );
+
+    let mut valid = true;
+
+    if layout.rooms.len() < mode.players() {
+        eprintln!(
+            "the dungeon needs a room for each of the {} players to spawn in",
+            mode.players()
+        );
+        valid = false;
+    }
+
+    if !layout.is_connected() {
+        eprintln!("not every floor tile can be reached");
+        valid = false;
+    }
+
+    valid
+}
+
+pub struct DungeonPlugin;
+
+impl Plugin for DungeonPlugin {
+    fn build(&self, app_builder: &mut AppBuilder) {
+        let is_server = app_builder
+            .world()
+            .get_resource::<NetworkSettings>()
+            .unwrap()
+            .is_server;
+
+        app_builder.register_network_event::<DungeonEvent>();
+
+        app_builder.add_system_set(
+            SystemSet::on_exit(GameState::Dungeon).with_system(dungeon_exit_system.system()),
+        );
+
+        if is_server {
+            app_builder.add_system_set(
+                SystemSet::on_enter(GameState::Dungeon)
+                    .with_system(dungeon_start_server_system.system()),
+            );
+            app_builder.add_system_set(
+                SystemSet::on_update(GameState::Dungeon)
+                    .with_system(dungeon_server_system.system().label("dungeon")),
+            );
+            app_builder.add_system_set(
+                SystemSet::on_exit(GameState::Dungeon)
+                    .with_system(dungeon_exit_server_system.system()),
+            );
+        } else {
+            app_builder.add_system(dungeon_client_system.system());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn generated_dungeons_are_connected() {
+        let params = DungeonParams::default();
+
+        for seed in 0..1000 {
+            let layout = DungeonLayout::generate(seed, &params);
+
+            assert!(layout.is_connected(), "seed {} isn't connected", seed);
+            assert!(
+                layout.spawn_points().len() >= 2,
+                "seed {} has fewer than two spawn points",
+                seed
+            );
+        }
+    }
+
+    #[test]
+    fn enemies_and_chests_avoid_the_spawn_rooms() {
+        let params = DungeonParams::default();
+
+        for seed in 0..1000 {
+            let layout = DungeonLayout::generate(seed, &params);
+
+            for &players in &[2, 4] {
+                let order = layout.spawn_order();
+                let spawn_rooms = &order[..players.min(order.len())];
+
+                let in_spawn_room = |position: Vec2| {
+                    spawn_rooms.iter().any(|room| {
+                        let room = layout.rooms[*room];
+                        let min = layout.tile_position(room.x, room.y);
+                        let max = layout.tile_position(room.x + room.width, room.y + room.height);
+
+                        position.x >= min.x
+                            && position.y >= min.y
+                            && position.x < max.x
+                            && position.y < max.y
+                    })
+                };
+
+                for (enemy, position) in layout.enemy_spawns(&params, players) {
+                    assert!(
+                        !in_spawn_room(position),
+                        "seed {} spawns '{}' in a spawn room of {} players",
+                        seed,
+                        enemy,
+                        players
+                    );
+                }
+
+                for position in layout.chest_spawns(&params, players) {
+                    assert!(
+                        !in_spawn_room(position),
+                        "seed {} spawns a chest in a spawn room of {} players",
+                        seed,
+                        players
+                    );
+                }
+            }
+        }
+    }
+}
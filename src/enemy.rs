@@ -0,0 +1,269 @@
+//! Enemies in the dungeon, controlled by the server.
+//!
+//! Enemies are players without a client, their health, speed and attacks come
+//! from a [`Frame`]. They send [`PlayerInputEvent`]s like bots do, so they hit
+//! and get hit through the same systems as players.
+
+use crate::attack::*;
+use crate::bot::*;
+use crate::frame::*;
+use crate::game_settings::*;
+use crate::game_state::*;
+use crate::item::*;
+use crate::networking::*;
+use crate::player::*;
+use bevy::prelude::*;
+
+/// How close an enemy has to get to where it spawned before it stops returning.
+const RETURN_DISTANCE: f32 = 4.0;
+
+#[derive(Serialize, Deserialize, TypeUuid)]
+#[serde(deny_unknown_fields)]
+#[uuid = "3e8b7c52-d0a4-4f19-b6e1-9a25c4d07f83"]
+pub struct Enemy {
+    pub name: String,
+    /// The frame with the health, speed and attacks of the enemy.
+    pub frame: String,
+    /// How close a player has to get for the enemy to start chasing them.
+    pub aggro_radius: f32,
+    /// How far from where it spawned the enemy chases a player, before giving up and returning.
+    pub leash_radius: f32,
+    /// The number of frames the enemy waits between attacks.
+    pub attack_cooldown: u32,
+    /// The items the enemy might drop when it dies.
+    #[serde(default)]
+    pub loot: Vec<LootDrop>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum EnemyState {
+    Idle,
+    Chase(Entity),
+    /// Walking back to where it spawned, ignoring players.
+    Return,
+}
+
+/// The AI of an enemy, only on the server.
+pub struct EnemyController {
+    pub enemy: String,
+    pub state: EnemyState,
+    /// Where the enemy spawned.
+    home: Vec2,
+    cooldown: u32,
+    pub dropped_loot: bool,
+}
+
+impl EnemyController {
+    pub fn new(enemy: String, home: Vec2) -> Self {
+        Self {
+            enemy,
+            state: EnemyState::Idle,
+            home,
+            cooldown: 0,
+            dropped_loot: false,
+        }
+    }
+}
+
+#[derive(Clone, Serialize, Deserialize, TypeUuid)]
+#[uuid = "a4f26d93-1b7e-4c58-8d0a-e3b59f1c62d7"]
+pub struct EnemySpawner {
+    /// The path of the [`Enemy`].
+    pub enemy: String,
+    pub frame: String,
+    /// The actor the enemy plays as, reserved on the server.
+    pub actor_id: ActorId,
+    pub position: Vec2,
+}
+
+impl NetworkSpawnable for EnemySpawner {
+    fn spawn(&self, world: &mut World) -> Entity {
+        let entity = PlayerSpawner {
+            frame: self.frame.clone(),
+            player_id: self.actor_id,
+            team: Team::ENEMIES,
+            position: self.position,
+        }
+        .spawn(world);
+
+        if world.get_resource::<NetworkSettings>().unwrap().is_server {
+            world
+                .entity_mut(entity)
+                .insert(EnemyController::new(self.enemy.clone(), self.position));
+        }
+
+        entity
+    }
+}
+
+/// Spawns an enemy on the server and every client.
+pub fn spawn_enemy(
+    enemies: &Assets<Enemy>,
+    net: &mut NetworkResource,
+    network_spawner: &NetworkSpawner,
+    enemy: &str,
+    position: Vec2,
+) {
+    let frame = enemies.get(enemy).unwrap().frame.clone();
+
+    network_spawner.spawn(EnemySpawner {
+        enemy: String::from(enemy),
+        frame,
+        actor_id: net.reserve_id(),
+        position,
+    });
+}
+
+pub fn enemy_system(
+    frames: Res<Assets<Frame>>,
+    attacks: Res<Assets<Attack>>,
+    enemies: Res<Assets<Enemy>>,
+    mut events: ResMut<NetworkEvents<PlayerInputEvent>>,
+    mut enemy_query: Query<
+        (&NetworkEntity, &mut Player, &Transform, &mut EnemyController),
+        With<EnemyController>,
+    >,
+    target_query: Query<(Entity, &Player, &Transform), Without<EnemyController>>,
+) {
+    for (network_entity, mut player, transform, mut controller) in enemy_query.iter_mut() {
+        if player.dead {
+            continue;
+        }
+
+        let enemy = enemies.get(controller.enemy.as_str()).unwrap();
+        let frame = frames.get(&player.frame).unwrap();
+        let attack = attacks.get(frame.light_attack.as_str()).unwrap();
+        let position = transform.translation.truncate();
+        let home = controller.home;
+
+        controller.cooldown = controller.cooldown.saturating_sub(1);
+
+        controller.state = match controller.state {
+            EnemyState::Idle => {
+                // the closest living player within the aggro radius
+                let target = target_query
+                    .iter()
+                    .filter(|(_, target, _)| !target.dead)
+                    .map(|(entity, _, transform)| {
+                        (entity, transform.translation.truncate().distance(position))
+                    })
+                    .filter(|(_, distance)| *distance <= enemy.aggro_radius)
+                    .min_by(|(_, a), (_, b)| a.partial_cmp(b).unwrap());
+
+                match target {
+                    Some((entity, _)) => EnemyState::Chase(entity),
+                    None => EnemyState::Idle,
+                }
+            }
+            EnemyState::Chase(entity) => match target_query.get(entity) {
+                Ok((_, target, transform))
+                    if !target.dead
+                        && transform.translation.truncate().distance(home)
+                            <= enemy.leash_radius =>
+                {
+                    EnemyState::Chase(entity)
+                }
+                _ => EnemyState::Return,
+            },
+            EnemyState::Return => {
+                if position.distance(home) <= RETURN_DISTANCE {
+                    // heal when back, so players can't wear it down by pulling it out of the leash
+                    player.health = frame.max_health;
+                    player.update_health = true;
+
+                    EnemyState::Idle
+                } else {
+                    EnemyState::Return
+                }
+            }
+        };
+
+        let mut movement = Vec2::ZERO;
+
+        match controller.state {
+            EnemyState::Idle => {}
+            EnemyState::Chase(entity) => {
+                let (_, target, target_transform) = target_query.get(entity).unwrap();
+                let target_frame = frames.get(&target.frame).unwrap();
+
+                let offset = target_transform.translation.truncate() - position;
+                let in_range = offset.length() <= attack.reach() + target_frame.collider_radius;
+
+                if !in_range {
+                    movement = offset.normalize_or_zero();
+                }
+
+                // the aim direction points away from the target
+                if offset != Vec2::ZERO {
+                    events.push(
+                        player.actor_id,
+                        PlayerInputEvent::SetAimDirection(*network_entity, -offset.normalize()),
+                    );
+                }
+
+                let can_attack = !player.attacking
+                    && player.stun.is_none()
+                    && player.stamina >= attack.stamina_cost;
+
+                if in_range && can_attack && controller.cooldown == 0 {
+                    controller.cooldown = enemy.attack_cooldown;
+
+                    events.push(
+                        player.actor_id,
+                        PlayerInputEvent::Attack(*network_entity, AttackType::LightAttack),
+                    );
+                }
+            }
+            EnemyState::Return => {
+                movement = (home - position).normalize_or_zero();
+            }
+        }
+
+        if movement != player.movement_vector {
+            events.push(
+                player.actor_id,
+                PlayerInputEvent::SetMovement(*network_entity, movement),
+            );
+        }
+    }
+}
+
+/// Despawns every enemy when leaving the dungeon.
+pub fn enemy_exit_server_system(
+    network_spawner: Res<NetworkSpawner>,
+    query: Query<&NetworkEntity, With<EnemyController>>,
+) {
+    for network_entity in query.iter() {
+        network_spawner.despawn(*network_entity);
+    }
+}
+
+pub struct EnemyLoader;
+
+crate::ron_loader!(EnemyLoader, "enm" => Enemy);
+
+/// Adds enemies, must be added after the [`BotPlugin`], which adds the [`BotStage`].
+pub struct EnemyPlugin;
+
+impl Plugin for EnemyPlugin {
+    fn build(&self, app_builder: &mut AppBuilder) {
+        let is_server = app_builder
+            .world()
+            .get_resource::<NetworkSettings>()
+            .unwrap()
+            .is_server;
+
+        app_builder.add_asset::<Enemy>();
+        app_builder.add_asset_loader(EnemyLoader);
+
+        app_builder.register_network_spawnable::<EnemySpawner>();
+
+        if is_server {
+            app_builder.add_system_to_stage(BotStage::Think, enemy_system.system());
+            app_builder.add_system_set(
+                SystemSet::on_exit(GameState::Dungeon)
+                    .with_system(enemy_exit_server_system.system()),
+            );
+        }
+    }
+}
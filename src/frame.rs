@@ -2,14 +2,32 @@ use crate::attack::*;
 use crate::networking::*;
 use bevy::prelude::*;
 
-#[derive(Serialize, Deserialize, TypeUuid)]
+#[derive(Clone, Serialize, Deserialize, TypeUuid)]
+#[serde(deny_unknown_fields)]
 #[uuid = "0dd5b51e-b5b4-4c84-8eec-c2e72d0cb0e8"]
 pub struct Frame {
     pub name: String,
     pub max_health: f32,
     pub walking_speed: f32,
     pub collider_radius: f32,
-    pub idle_animation: String,
+    pub max_stamina: f32,
+    /// Stamina regenerated per frame while not attacking.
+    pub stamina_regen: f32,
+    pub max_guard: f32,
+    /// Guard regenerated per frame while not blocking.
+    pub guard_regen: f32,
+    pub guard_break_stun: u32,
+    /// Multiplier applied to the damage of blocked hits.
+    pub block_damage_multiplier: f32,
+    /// The number of frames after starting to block, in which a hit is parried.
+    pub parry_window: u32,
+    /// The stun applied to an attacker when parried.
+    pub parry_stun: u32,
+    /// Multiplier applied to the damage of every attack.
+    #[serde(default = "crate::helper::one")]
+    pub damage_multiplier: f32,
+    /// The path to the animation state machine of the frame.
+    pub animation_states: String,
     pub light_attack: String,
 }
 
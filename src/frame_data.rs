@@ -0,0 +1,289 @@
+//! Frame data tables of every move, printed with `--frame-data`.
+//!
+//! All values are in server ticks, animation frames are converted with the
+//! animation's fps and [`TICK_RATE`].
+
+use crate::angle::*;
+use crate::animation::*;
+use crate::asset_check::*;
+use crate::attack::*;
+use crate::frame::*;
+use crate::server::TICK_RATE;
+use std::collections::{HashMap, HashSet};
+
+/// The frame data of a single move.
+pub struct MoveData {
+    pub frame: String,
+    pub attack_type: AttackType,
+    pub attack: String,
+    /// The tick the first hitbox becomes active on.
+    pub startup: Option<u32>,
+    /// The ticks from the first hitbox appearing to the last one ending.
+    pub active: Option<u32>,
+    /// The ticks after the last hitbox ends before the player can act again.
+    pub recovery: Option<u32>,
+    /// The ticks the player can't act for, including self-stun.
+    pub total: u32,
+    /// The longest stun the move applies to its user.
+    pub self_stun: u32,
+    pub damage: f32,
+    pub stun: Option<u32>,
+    pub block_stun: Option<u32>,
+    /// The ticks the attacker can act before the defender, when the last hitbox connects on its first tick.
+    pub on_hit: Option<i32>,
+    pub on_block: Option<i32>,
+    pub stamina_cost: f32,
+}
+
+/// Converts animation frames to ticks, rounded up.
+pub fn ticks(frames: u32, fps: f32) -> u32 {
+    (frames as f32 * TICK_RATE / fps).ceil() as u32
+}
+
+/// Calculates the frame data of an attack.
+///
+/// Returns `None` if the attack or any animation it references is missing.
+pub fn move_data(
+    frame: &Frame,
+    attack_type: AttackType,
+    attacks: &HashMap<String, Attack>,
+    animations: &HashMap<String, Animation>,
+) -> Option<MoveData> {
+    let attack_path = frame.get_attack(&attack_type);
+    let attack = attacks.get(attack_path)?;
+    // every angle is played the same, so any of them will do
+    let (animation_path, _) = attack.animation.get(Angle::Down);
+    let animation = animations.get(animation_path)?;
+
+    let mut event_frames = attack.events.keys().collect::<Vec<_>>();
+    event_frames.sort();
+
+    let mut busy = ticks(animation.length, animation.fps);
+    let mut self_stun = 0;
+    let mut damage = 0.0;
+    // the first tick a hitbox is active on and the tick after the last one ends
+    let mut active: Option<(u32, u32)> = None;
+    // the tick the last hitbox appears on and its stun and block stun
+    let mut last_hit: Option<(u32, u32, u32)> = None;
+
+    for event_frame in event_frames {
+        let tick = ticks(*event_frame, animation.fps);
+
+        for event in &attack.events[event_frame] {
+            let (hit_damage, stun, block_stun, duration) = match event {
+                AttackEvent::ActivateHitbox {
+                    damage,
+                    stun,
+                    block_stun,
+                    animation,
+                    ..
+                } => {
+                    let hit_animation = animations.get(animation)?;
+                    let duration = ticks(hit_animation.length, hit_animation.fps);
+
+                    (*damage, *stun, *block_stun, duration)
+                }
+                AttackEvent::SpawnProjectile {
+                    damage,
+                    stun,
+                    block_stun,
+                    lifetime,
+                    ..
+                } => (*damage, *stun, *block_stun, *lifetime),
+                AttackEvent::Stun(duration) => {
+                    self_stun = self_stun.max(*duration);
+                    busy = busy.max(tick + duration);
+                    continue;
+                }
+                _ => continue,
+            };
+
+            damage += hit_damage;
+            last_hit = Some((tick, stun, block_stun));
+            active = Some(match active {
+                Some((start, end)) => (start, end.max(tick + duration)),
+                None => (tick, tick + duration),
+            });
+        }
+    }
+
+    let advantage = |stun: u32, tick: u32| stun as i32 - (busy as i32 - tick as i32);
+
+    Some(MoveData {
+        frame: frame.name.clone(),
+        attack_type,
+        attack: attack_path.clone(),
+        startup: active.map(|(start, _)| start + 1),
+        active: active.map(|(start, end)| end - start),
+        recovery: active.map(|(_, end)| busy.saturating_sub(end)),
+        total: busy,
+        self_stun,
+        damage,
+        stun: last_hit.map(|(_, stun, _)| stun),
+        block_stun: last_hit.map(|(_, _, block_stun)| block_stun),
+        on_hit: last_hit.map(|(tick, stun, _)| advantage(stun, tick)),
+        on_block: last_hit.map(|(tick, _, block_stun)| advantage(block_stun, tick)),
+        stamina_cost: attack.stamina_cost,
+    })
+}
+
+const HEADER: [&str; 13] = [
+    "Move",
+    "Attack",
+    "Startup",
+    "Active",
+    "Recovery",
+    "Total",
+    "Self-stun",
+    "Damage",
+    "Stun",
+    "Block stun",
+    "On hit",
+    "On block",
+    "Stamina",
+];
+
+fn row(move_data: &MoveData, missing: &str) -> Vec<String> {
+    let optional = |value: Option<String>| value.unwrap_or_else(|| String::from(missing));
+
+    vec![
+        format!("{:?}", move_data.attack_type),
+        move_data.attack.clone(),
+        optional(move_data.startup.map(|v| v.to_string())),
+        optional(move_data.active.map(|v| v.to_string())),
+        optional(move_data.recovery.map(|v| v.to_string())),
+        move_data.total.to_string(),
+        move_data.self_stun.to_string(),
+        move_data.damage.to_string(),
+        optional(move_data.stun.map(|v| v.to_string())),
+        optional(move_data.block_stun.map(|v| v.to_string())),
+        optional(move_data.on_hit.map(|v| format!("{:+}", v))),
+        optional(move_data.on_block.map(|v| format!("{:+}", v))),
+        move_data.stamina_cost.to_string(),
+    ]
+}
+
+/// Formats the moves as markdown, with a table per frame.
+pub fn markdown(moves: &[MoveData]) -> String {
+    let mut output = String::new();
+    let mut current_frame = None;
+
+    for move_data in moves {
+        if current_frame != Some(&move_data.frame) {
+            current_frame = Some(&move_data.frame);
+
+            if !output.is_empty() {
+                output.push('\n');
+            }
+
+            output += &format!("## {}\n\n", move_data.frame);
+            output += &format!("| {} |\n", HEADER.join(" | "));
+            output += &format!("|{}\n", "---|".repeat(HEADER.len()));
+        }
+
+        output += &format!("| {} |\n", row(move_data, "-").join(" | "));
+    }
+
+    output
+}
+
+/// Formats the moves as a single csv table, with the frame in the first column.
+pub fn csv(moves: &[MoveData]) -> String {
+    let mut output = format!("Frame,{}\n", HEADER.join(","));
+
+    for move_data in moves {
+        let fields = std::iter::once(move_data.frame.clone())
+            .chain(row(move_data, ""))
+            .map(|field| {
+                if field.contains(|c: char| c == ',' || c == '"' || c == '\n') {
+                    format!("\"{}\"", field.replace('"', "\"\""))
+                } else {
+                    field
+                }
+            })
+            .collect::<Vec<_>>();
+
+        output += &format!("{}\n", fields.join(","));
+    }
+
+    output
+}
+
+/// Loads the assets in `root` and prints the frame data of every move in `format`.
+///
+/// Returns false if the assets of any move are invalid.
+pub fn print_frame_data(root: &str, format: &str) -> bool {
+    let mut checker = AssetChecker::new(root);
+
+    if let Err(e) = checker.check() {
+        eprintln!("failed to read '{}': {}", root, e);
+        return false;
+    }
+
+    // only the frames, their attacks and the animations those play end up in the tables,
+    // so a broken map or item doesn't stop the export
+    let mut exported = HashSet::new();
+
+    for (path, frame) in checker.frames() {
+        exported.insert(path.as_str());
+
+        for attack_type in AttackType::ALL.iter() {
+            let attack_path = frame.get_attack(attack_type);
+            exported.insert(attack_path.as_str());
+
+            if let Some(attack) = checker.attacks().get(attack_path) {
+                exported.extend(attack.animation.paths().into_iter().map(String::as_str));
+
+                for event in attack.events.values().flatten() {
+                    if let AttackEvent::ActivateHitbox { animation, .. } = event {
+                        exported.insert(animation.as_str());
+                    }
+                }
+            }
+        }
+    }
+
+    // errors start with the path of their file, frames that don't parse are missing above
+    let errors = checker
+        .errors()
+        .iter()
+        .filter(|error| {
+            let path = error.split(':').next().unwrap_or_default();
+            exported.contains(path) || path.ends_with(".fme")
+        })
+        .collect::<Vec<_>>();
+
+    if !errors.is_empty() {
+        for error in errors {
+            eprintln!("{}", error);
+        }
+
+        return false;
+    }
+
+    let mut frame_paths = checker.frames().keys().collect::<Vec<_>>();
+    frame_paths.sort();
+
+    let mut moves = Vec::new();
+
+    for path in frame_paths {
+        let frame = &checker.frames()[path];
+
+        for attack_type in AttackType::ALL.iter() {
+            // the references were checked above
+            moves.extend(move_data(
+                frame,
+                *attack_type,
+                checker.attacks(),
+                checker.animations(),
+            ));
+        }
+    }
+
+    match format {
+        "csv" => print!("{}", csv(&moves)),
+        _ => print!("{}", markdown(&moves)),
+    }
+
+    true
+}
@@ -1,12 +1,152 @@
 use crate::networking::*;
+use bevy::prelude::*;
 
-#[derive(Serialize, Deserialize)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub enum GameMode {
     OneVersusOne,
+    /// Two teams of two players.
+    TwoVersusTwo,
+    /// Four players, each on their own team.
+    FreeForAll,
+    /// Four players, each on their own team, fighting over a hill in the middle of the arena.
+    /// The first to stand on the hill alone for long enough wins the round.
+    KingOfTheHill,
+    /// A single player against a training dummy.
+    Training,
 }
 
-#[derive(Serialize, Deserialize, TypeUuid)]
+impl GameMode {
+    /// The number of players needed to start the game, including bots.
+    pub fn players(&self) -> usize {
+        match self {
+            GameMode::OneVersusOne | GameMode::Training => 2,
+            GameMode::TwoVersusTwo | GameMode::FreeForAll | GameMode::KingOfTheHill => 4,
+        }
+    }
+
+    /// The team of the player in `slot`, slots are given out in the order players connect.
+    pub fn team(&self, slot: usize) -> Team {
+        match self {
+            GameMode::TwoVersusTwo => Team(slot as u32 % 2),
+            _ => Team(slot as u32),
+        }
+    }
+
+    /// Where the player in `slot` spawns.
+    pub fn spawn_position(&self, slot: usize) -> Vec2 {
+        let positions: &[Vec2] = match self {
+            GameMode::OneVersusOne | GameMode::Training => {
+                &[Vec2::new(-100.0, 0.0), Vec2::new(100.0, 0.0)]
+            }
+            // teammates next to each other, facing the other team
+            GameMode::TwoVersusTwo => &[
+                Vec2::new(-100.0, 40.0),
+                Vec2::new(100.0, 40.0),
+                Vec2::new(-100.0, -40.0),
+                Vec2::new(100.0, -40.0),
+            ],
+            GameMode::FreeForAll => &[
+                Vec2::new(-100.0, 0.0),
+                Vec2::new(100.0, 0.0),
+                Vec2::new(0.0, 100.0),
+                Vec2::new(0.0, -100.0),
+            ],
+            // away from the hill, at the same distance
+            GameMode::KingOfTheHill => &[
+                Vec2::new(-140.0, 0.0),
+                Vec2::new(140.0, 0.0),
+                Vec2::new(0.0, 140.0),
+                Vec2::new(0.0, -140.0),
+            ],
+        };
+
+        positions[slot % positions.len()]
+    }
+}
+
+impl std::str::FromStr for GameMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "1v1" => Ok(GameMode::OneVersusOne),
+            "2v2" => Ok(GameMode::TwoVersusTwo),
+            "ffa" => Ok(GameMode::FreeForAll),
+            "koth" => Ok(GameMode::KingOfTheHill),
+            _ => Err(format!("unknown game mode '{}'", s)),
+        }
+    }
+}
+
+/// The team a player is on, players can't hurt their own team unless friendly fire is on.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct Team(pub u32);
+
+impl Team {
+    /// The team of the enemies in the dungeon.
+    pub const ENEMIES: Team = Team(u32::MAX);
+}
+
+/// The map used if none is configured.
+pub const DEFAULT_MAP: &str = "maps/arena.map";
+
+/// Chosen when starting the server, and sent to every client when they connect.
+#[derive(Clone, Debug, Serialize, Deserialize, TypeUuid)]
 #[uuid = "4a559fd6-20c6-4d5e-85e8-3e5611b0987f"]
 pub struct GameSettings {
     pub mode: GameMode,
+    /// The match is best of `rounds`.
+    pub rounds: u32,
+    /// Whether players can hurt their teammates.
+    pub friendly_fire: bool,
+    /// The path of the [`Map`](crate::map::Map) duels are fought in.
+    pub map: String,
+    /// Play through a dungeon before the duel.
+    pub dungeon: bool,
+    /// The seed of the dungeon, a random one is used if this is `None`.
+    pub dungeon_seed: Option<u64>,
+}
+
+impl Default for GameSettings {
+    fn default() -> Self {
+        Self {
+            mode: GameMode::OneVersusOne,
+            rounds: 3,
+            friendly_fire: false,
+            map: String::from(DEFAULT_MAP),
+            dungeon: false,
+            dungeon_seed: None,
+        }
+    }
+}
+
+pub fn game_settings_client_system(
+    mut commands: Commands,
+    mut events: ResMut<NetworkEvents<GameSettings>>,
+) {
+    for (_sender, game_settings) in events.take() {
+        info!("Received game settings: {:?}", game_settings);
+
+        commands.insert_resource(game_settings);
+    }
+}
+
+pub struct GameSettingsPlugin;
+
+impl Plugin for GameSettingsPlugin {
+    fn build(&self, app_builder: &mut AppBuilder) {
+        let is_server = app_builder
+            .world()
+            .get_resource::<NetworkSettings>()
+            .unwrap()
+            .is_server;
+
+        app_builder.register_network_event::<GameSettings>();
+
+        if !is_server {
+            // replaced by the server's settings when connecting
+            app_builder.insert_resource(GameSettings::default());
+            app_builder.add_system(game_settings_client_system.system());
+        }
+    }
 }
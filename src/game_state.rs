@@ -15,4 +15,6 @@ pub enum GameState {
     Duel,
     /// After the duel has ended and the score screen is shown.
     Ended,
+    /// When a single player is training against a dummy.
+    Training,
 }
@@ -5,4 +5,69 @@ pub fn convex_hull(point: Vec<Vec2>) -> Body {
     let points = point.into_iter().map(|v| v.extend(0.0)).collect::<Vec<_>>();
 
     Body::ConvexHull { points }
-}
\ No newline at end of file
+}
+
+/// Returns true if two bodies overlap, only spheres and convex hulls are supported.
+///
+/// Convex hulls are treated as flat polygons, since the game is 2d.
+pub fn bodies_overlap(
+    a: &Body,
+    a_transform: &GlobalTransform,
+    b: &Body,
+    b_transform: &GlobalTransform,
+) -> bool {
+    match (a, b) {
+        (Body::Sphere { radius: a_radius }, Body::Sphere { radius: b_radius }) => {
+            let distance = a_transform
+                .translation
+                .truncate()
+                .distance(b_transform.translation.truncate());
+
+            distance < a_radius + b_radius
+        }
+        (Body::Sphere { radius }, Body::ConvexHull { points }) => {
+            let polygon = points
+                .iter()
+                .map(|point| b_transform.mul_vec3(*point).truncate())
+                .collect::<Vec<_>>();
+
+            circle_overlaps_polygon(a_transform.translation.truncate(), *radius, &polygon)
+        }
+        (Body::ConvexHull { .. }, Body::Sphere { .. }) => {
+            bodies_overlap(b, b_transform, a, a_transform)
+        }
+        _ => false,
+    }
+}
+
+fn circle_overlaps_polygon(center: Vec2, radius: f32, polygon: &[Vec2]) -> bool {
+    if polygon.len() < 3 {
+        return false;
+    }
+
+    let mut sign = 0.0;
+    let mut inside = true;
+
+    for i in 0..polygon.len() {
+        let a = polygon[i];
+        let b = polygon[(i + 1) % polygon.len()];
+
+        // closest point on the edge
+        let edge = b - a;
+        let t = ((center - a).dot(edge) / edge.length_squared()).clamp(0.0, 1.0);
+
+        if center.distance(a + edge * t) < radius {
+            return true;
+        }
+
+        let side = edge.perp_dot(center - a).signum();
+
+        if sign == 0.0 {
+            sign = side;
+        } else if side != sign {
+            inside = false;
+        }
+    }
+
+    inside
+}
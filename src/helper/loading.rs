@@ -1,6 +1,94 @@
+use serde::de::{self, Deserialize, DeserializeOwned, Deserializer, MapAccess, Visitor};
+use std::{collections::HashMap, fmt, hash::Hash, marker::PhantomData};
+
+/// Parses RON like [`ron::de::from_bytes`], but errors raised through serde,
+/// like missing fields, get the position where parsing stopped.
+pub fn parse_ron<T: DeserializeOwned>(bytes: &[u8]) -> ron::Result<T> {
+    let mut deserializer = ron::de::Deserializer::from_bytes(bytes)?;
+
+    let result = T::deserialize(&mut deserializer).and_then(|asset| {
+        deserializer.end()?;
+        Ok(asset)
+    });
+
+    result.map_err(|mut e| {
+        if e.position == (ron::error::Position { line: 0, col: 0 }) {
+            let offset = bytes.len().saturating_sub(deserializer.remainder().len());
+            e.position = position(bytes, offset);
+        }
+
+        e
+    })
+}
+
+/// Finds the line and column of a byte offset, both starting at 1.
+fn position(bytes: &[u8], offset: usize) -> ron::error::Position {
+    let before = &bytes[..offset.min(bytes.len())];
+    let line = before.iter().filter(|b| **b == b'\n').count() + 1;
+    let col = before.iter().rev().take_while(|b| **b != b'\n').count() + 1;
+
+    ron::error::Position { line, col }
+}
+
+/// Deserializes a map, failing on duplicate keys instead of silently keeping the last value.
+///
+/// Used with `#[serde(deserialize_with = "crate::helper::unique_map")]`.
+pub fn unique_map<'de, D, K, V>(deserializer: D) -> Result<HashMap<K, V>, D::Error>
+where
+    D: Deserializer<'de>,
+    K: Deserialize<'de> + Eq + Hash + fmt::Debug,
+    V: Deserialize<'de>,
+{
+    struct UniqueMapVisitor<K, V>(PhantomData<(K, V)>);
+
+    impl<'de, K, V> Visitor<'de> for UniqueMapVisitor<K, V>
+    where
+        K: Deserialize<'de> + Eq + Hash + fmt::Debug,
+        V: Deserialize<'de>,
+    {
+        type Value = HashMap<K, V>;
+
+        fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            f.write_str("a map with unique keys")
+        }
+
+        fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<Self::Value, A::Error> {
+            let mut values = HashMap::new();
+
+            while let Some(key) = map.next_key::<K>()? {
+                if values.contains_key(&key) {
+                    return Err(de::Error::custom(format!("duplicate key `{:?}`", key)));
+                }
+
+                let value = map.next_value()?;
+                values.insert(key, value);
+            }
+
+            Ok(values)
+        }
+    }
+
+    deserializer.deserialize_map(UniqueMapVisitor(PhantomData))
+}
+
+/// The default of multipliers, used with `#[serde(default = "crate::helper::one")]`.
+pub fn one() -> f32 {
+    1.0
+}
+
+/// Implements [`bevy::asset::AssetLoader`] for RON assets.
+///
+/// Assets are parsed with [`parse_ron`], so errors have a line and column.
+///
+/// Strict loading is opt-in per asset type, serde has no way to enforce it here:
+/// every struct read from an asset, nested ones included, should use
+/// `#[serde(deny_unknown_fields)]` and every map [`unique_map`]. Without them a misspelled
+/// field or a repeated key is silently ignored, by `--check-assets` as well.
+///
+/// Multiple extensions can load the same asset type, `"a" | "b" => Asset`.
 #[macro_export]
 macro_rules! ron_loader {
-    ($loader:path, $($extension:expr => $asset:path),+) => {
+    ($loader:path, $($($extension:literal)|+ => $asset:path),+) => {
         impl bevy::asset::AssetLoader for $loader
         {
             fn load<'a>(
@@ -9,10 +97,16 @@ macro_rules! ron_loader {
                 load_context: &'a mut bevy::asset::LoadContext,
             ) -> bevy::utils::BoxedFuture<'a, Result<(), anyhow::Error>> {
                 Box::pin(async move {
-                    match load_context.path().extension().unwrap().to_str().unwrap() {
+                    let extension = load_context
+                        .path()
+                        .extension()
+                        .and_then(|extension| extension.to_str())
+                        .unwrap_or("");
+
+                    match extension {
                         $(
-                            $extension => {
-                                let asset = ron::de::from_bytes::<$asset>(bytes)
+                            $($extension)|+ => {
+                                let asset = $crate::helper::parse_ron::<$asset>(bytes)
                                     .map_err(|e| {
                                         anyhow::Error::msg(
                                             format!("'{}': {}", load_context.path().to_string_lossy(), e)
@@ -21,7 +115,13 @@ macro_rules! ron_loader {
                                 load_context.set_default_asset(bevy::asset::LoadedAsset::new(asset));
                             },
                         )+
-                        e => unreachable!("{:?}", e),
+                        e => {
+                            return Err(anyhow::Error::msg(format!(
+                                "'{}': unsupported extension '{}'",
+                                load_context.path().to_string_lossy(),
+                                e
+                            )));
+                        }
                     }
 
                     Ok(())
@@ -30,7 +130,7 @@ macro_rules! ron_loader {
 
             fn extensions(&self) -> &[&str] {
                 &[
-                    $($extension,)+
+                    $($($extension,)+)+
                 ]
             }
         }
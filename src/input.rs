@@ -2,6 +2,7 @@ use bevy::{prelude::*, reflect::TypeUuid};
 use serde::{Deserialize, Serialize};
 
 #[derive(Serialize, Deserialize, TypeUuid)]
+#[serde(deny_unknown_fields)]
 #[uuid = "0c4e97f6-10a1-4a92-98c7-07c81bebfe9a"]
 pub struct InputSettings {
     pub up: ButtonInput,
@@ -9,6 +10,14 @@ pub struct InputSettings {
     pub left: ButtonInput,
     pub right: ButtonInput,
     pub light_attack: ButtonInput,
+    pub block: ButtonInput,
+    /// Cycles the behaviour of the training dummy.
+    pub training_dummy: ButtonInput,
+    /// Starts or stops recording input for the training dummy to replay.
+    pub training_record: ButtonInput,
+    pub training_reset: ButtonInput,
+    /// Toggles drawing hitboxes, colliders, aim directions and network entities.
+    pub debug_render: ButtonInput,
 }
 
 #[derive(Serialize, Deserialize)]
@@ -36,6 +45,13 @@ impl ButtonInput {
             Self::Mouse(btn) => input.mouse.just_pressed(*btn),
         }
     }
+
+    pub fn just_released(&self, input: &InputCtx) -> bool {
+        match self {
+            Self::Key(key) => input.keyboard.just_released(*key),
+            Self::Mouse(btn) => input.mouse.just_released(*btn),
+        }
+    }
 }
 
 pub struct InputLoader;
@@ -0,0 +1,546 @@
+//! Items, found in the dungeon and applied to the player's frame before the duel.
+//!
+//! Inventories only exist on the server and are sent privately to their owner,
+//! the other players only see the resulting frame.
+
+use crate::enemy::*;
+use crate::frame::*;
+use crate::game_state::*;
+use crate::networking::*;
+use crate::player::*;
+use crate::progress_bar::*;
+use crate::round::*;
+use crate::server::TICK_RATE;
+use crate::transform::*;
+use bevy::prelude::*;
+use std::collections::HashSet;
+
+/// The distance from a player's collider an item or chest is picked up at.
+const PICKUP_RANGE: f32 = 8.0;
+/// The number of frames the items are shown for before the duel starts.
+const PRE_DUEL_TIME: u32 = TICK_RATE as u32 * 5;
+/// How far from an enemy or chest items are dropped.
+const DROP_SPREAD: f32 = 12.0;
+
+#[derive(Clone, Serialize, Deserialize)]
+pub enum ItemModifier {
+    /// Adds to the maximum health.
+    MaxHealth(f32),
+    MaxStamina(f32),
+    MaxGuard(f32),
+    /// Multiplies the walking speed.
+    WalkingSpeed(f32),
+    /// Multiplies the damage of every attack.
+    Damage(f32),
+    /// Replaces the light attack.
+    LightAttack(String),
+}
+
+impl ItemModifier {
+    pub fn apply(&self, frame: &mut Frame) {
+        match self {
+            ItemModifier::MaxHealth(health) => frame.max_health += health,
+            ItemModifier::MaxStamina(stamina) => frame.max_stamina += stamina,
+            ItemModifier::MaxGuard(guard) => frame.max_guard += guard,
+            ItemModifier::WalkingSpeed(multiplier) => frame.walking_speed *= multiplier,
+            ItemModifier::Damage(multiplier) => frame.damage_multiplier *= multiplier,
+            ItemModifier::LightAttack(attack) => frame.light_attack = attack.clone(),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, TypeUuid)]
+#[serde(deny_unknown_fields)]
+#[uuid = "5f0c9e27-b34a-4d81-a6c2-18e7d94b3f60"]
+pub struct Item {
+    pub name: String,
+    pub description: String,
+    /// The image shown when the item is on the ground.
+    pub icon: String,
+    pub modifiers: Vec<ItemModifier>,
+}
+
+/// An item that might drop from an enemy or chest.
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct LootDrop {
+    pub item: String,
+    /// The chance of the item dropping, between 0 and 1.
+    pub chance: f32,
+}
+
+/// The random number generator for loot on the server.
+pub struct Loot {
+    rng: u32,
+}
+
+impl Loot {
+    pub fn new() -> Self {
+        Self {
+            // xorshift can't be seeded with zero
+            rng: crate::dungeon::random_seed() as u32 | 1,
+        }
+    }
+
+    /// Returns a pseudo random number between 0 and 1.
+    fn random(&mut self) -> f32 {
+        self.rng ^= self.rng << 13;
+        self.rng ^= self.rng >> 17;
+        self.rng ^= self.rng << 5;
+
+        self.rng as f32 / u32::MAX as f32
+    }
+
+    /// Rolls every drop in a loot table, returning the items that dropped.
+    pub fn roll(&mut self, table: &[LootDrop]) -> Vec<String> {
+        table
+            .iter()
+            .filter(|drop| self.random() < drop.chance)
+            .map(|drop| drop.item.clone())
+            .collect()
+    }
+
+    /// Returns a random position around `position`, so drops don't stack.
+    fn scatter(&mut self, position: Vec2) -> Vec2 {
+        let angle = self.random() * std::f32::consts::TAU;
+
+        position + Vec2::new(angle.cos(), angle.sin()) * self.random() * DROP_SPREAD
+    }
+}
+
+/// The items a player has picked up, only on the server.
+pub struct Inventory {
+    pub items: Vec<String>,
+}
+
+impl Inventory {
+    pub fn new() -> Self {
+        Self { items: Vec::new() }
+    }
+}
+
+/// Sent privately to a player when their inventory changes.
+#[derive(Serialize, Deserialize, TypeUuid)]
+#[uuid = "b81d4f6a-2e93-4c07-9a5d-c46e0f8b1d29"]
+pub enum InventoryEvent {
+    SetItems(Vec<String>),
+}
+
+#[derive(Serialize, Deserialize, TypeUuid)]
+#[uuid = "0e6a3b95-d72c-4f18-8b4e-91f5a2c7d063"]
+pub enum ItemEvent {
+    /// The frame of a player, with their items applied, sent for every player when the dungeon ends.
+    SetFrame(NetworkEntity, Frame),
+}
+
+/// An item on the ground.
+pub struct ItemPickup {
+    pub item: String,
+}
+
+/// A chest in the dungeon, dropping its loot when a player touches it.
+pub struct Chest {
+    pub loot: Vec<LootDrop>,
+}
+
+#[derive(Clone, Serialize, Deserialize, TypeUuid)]
+#[uuid = "7c2e58f1-a06d-4b93-b5e7-3d1f84a9c6e2"]
+pub struct ItemPickupSpawner {
+    pub item: String,
+    pub icon: String,
+    pub position: Vec2,
+}
+
+impl NetworkSpawnable for ItemPickupSpawner {
+    fn spawn(&self, world: &mut World) -> Entity {
+        let transform = Transform::from_translation(self.position.extend(0.0));
+
+        if world.get_resource::<NetworkSettings>().unwrap().is_server {
+            world
+                .spawn()
+                .insert(ItemPickup {
+                    item: self.item.clone(),
+                })
+                .insert(transform)
+                .insert(GlobalTransform::default())
+                .id()
+        } else {
+            let material = sprite_material(world, &self.icon);
+
+            world
+                .spawn_bundle(SpriteBundle {
+                    material,
+                    transform,
+                    ..Default::default()
+                })
+                .insert(ZSort)
+                .id()
+        }
+    }
+}
+
+#[derive(Clone, Serialize, Deserialize, TypeUuid)]
+#[uuid = "e4a91c37-5b08-4f6d-92c1-a8d7f3e06b54"]
+pub struct ChestSpawner {
+    pub loot: Vec<LootDrop>,
+    pub position: Vec2,
+}
+
+impl NetworkSpawnable for ChestSpawner {
+    fn spawn(&self, world: &mut World) -> Entity {
+        let transform = Transform::from_translation(self.position.extend(0.0));
+
+        if world.get_resource::<NetworkSettings>().unwrap().is_server {
+            world
+                .spawn()
+                .insert(Chest {
+                    loot: self.loot.clone(),
+                })
+                .insert(transform)
+                .insert(GlobalTransform::default())
+                .id()
+        } else {
+            let material = sprite_material(world, "items/chest.png");
+
+            world
+                .spawn_bundle(SpriteBundle {
+                    material,
+                    transform,
+                    ..Default::default()
+                })
+                .insert(ZSort)
+                .id()
+        }
+    }
+}
+
+fn sprite_material(world: &mut World, image: &str) -> Handle<ColorMaterial> {
+    let texture = world.get_resource::<AssetServer>().unwrap().load(image);
+
+    world
+        .get_resource_mut::<Assets<ColorMaterial>>()
+        .unwrap()
+        .add(texture.into())
+}
+
+fn spawn_drops(
+    loot: &mut Loot,
+    items: &Assets<Item>,
+    network_spawner: &NetworkSpawner,
+    table: &[LootDrop],
+    position: Vec2,
+) {
+    for item in loot.roll(table) {
+        let icon = items.get(item.as_str()).unwrap().icon.clone();
+
+        network_spawner.spawn(ItemPickupSpawner {
+            item,
+            icon,
+            position: loot.scatter(position),
+        });
+    }
+}
+
+/// Gives every player an inventory, enemies don't pick up items.
+pub fn inventory_server_system(
+    mut commands: Commands,
+    query: Query<Entity, (With<Player>, Without<Inventory>, Without<EnemyController>)>,
+) {
+    for entity in query.iter() {
+        commands.entity(entity).insert(Inventory::new());
+    }
+}
+
+/// Drops loot from dead enemies and opened chests, and lets players pick items up.
+pub fn loot_server_system(
+    mut loot: ResMut<Loot>,
+    items: Res<Assets<Item>>,
+    enemies: Res<Assets<Enemy>>,
+    frames: Res<Assets<Frame>>,
+    network_spawner: Res<NetworkSpawner>,
+    event_sender: Res<NetworkEventSender>,
+    mut enemy_query: Query<(&Player, &Transform, &mut EnemyController)>,
+    mut player_query: Query<(&Player, &Transform, &mut Inventory)>,
+    pickup_query: Query<(&NetworkEntity, &ItemPickup, &Transform)>,
+    chest_query: Query<(&NetworkEntity, &Chest, &Transform)>,
+) {
+    for (player, transform, mut controller) in enemy_query.iter_mut() {
+        if !player.dead || controller.dropped_loot {
+            continue;
+        }
+
+        controller.dropped_loot = true;
+
+        let enemy = enemies.get(controller.enemy.as_str()).unwrap();
+
+        spawn_drops(
+            &mut loot,
+            &items,
+            &network_spawner,
+            &enemy.loot,
+            transform.translation.truncate(),
+        );
+    }
+
+    // the pickups and chests are despawned at the end of the frame
+    let mut taken = HashSet::new();
+
+    for (player, transform, mut inventory) in player_query.iter_mut() {
+        if player.dead {
+            continue;
+        }
+
+        let frame = frames.get(&player.frame).unwrap();
+        let position = transform.translation.truncate();
+        let range = frame.collider_radius + PICKUP_RANGE;
+
+        for (network_entity, chest, chest_transform) in chest_query.iter() {
+            let chest_position = chest_transform.translation.truncate();
+
+            if chest_position.distance(position) > range || !taken.insert(*network_entity) {
+                continue;
+            }
+
+            spawn_drops(
+                &mut loot,
+                &items,
+                &network_spawner,
+                &chest.loot,
+                chest_position,
+            );
+            network_spawner.despawn(*network_entity);
+        }
+
+        let mut picked_up = false;
+
+        for (network_entity, pickup, pickup_transform) in pickup_query.iter() {
+            if pickup_transform.translation.truncate().distance(position) > range
+                || !taken.insert(*network_entity)
+            {
+                continue;
+            }
+
+            inventory.items.push(pickup.item.clone());
+            network_spawner.despawn(*network_entity);
+            picked_up = true;
+        }
+
+        if picked_up {
+            event_sender
+                .send_to(
+                    player.actor_id,
+                    &InventoryEvent::SetItems(inventory.items.clone()),
+                )
+                .unwrap();
+        }
+    }
+}
+
+/// Applies the items to the players' frames and resets them for the duel.
+pub fn item_apply_server_system(
+    mut commands: Commands,
+    mut frames: ResMut<Assets<Frame>>,
+    items: Res<Assets<Item>>,
+    event_sender: Res<NetworkEventSender>,
+    inventory_query: Query<&Inventory>,
+    mut query: Query<ResetQuery>,
+) {
+    for (entity, network_entity, mut player, _, _, _, _, _) in query.iter_mut() {
+        // enemies don't have inventories, and are despawned at the end of the frame
+        let inventory = match inventory_query.get(entity) {
+            Ok(inventory) => inventory,
+            Err(_) => continue,
+        };
+
+        let mut frame = frames.get(&player.frame).unwrap().clone();
+
+        for path in &inventory.items {
+            for modifier in &items.get(path.as_str()).unwrap().modifiers {
+                modifier.apply(&mut frame);
+            }
+        }
+
+        event_sender
+            .send(&ItemEvent::SetFrame(*network_entity, frame.clone()))
+            .unwrap();
+
+        player.frame = frames.add(frame);
+    }
+
+    // start the duel at full health, with the new frames
+    reset_players(&mut commands, &frames, &event_sender, &mut query);
+}
+
+/// Starts the duel after the items have been shown.
+pub fn pre_duel_server_system(mut timer: Local<u32>, mut state: ResMut<State<GameState>>) {
+    *timer += 1;
+
+    if *timer >= PRE_DUEL_TIME {
+        *timer = 0;
+        state.set(GameState::Duel).unwrap();
+    }
+}
+
+/// Despawns the items and chests left in the dungeon.
+pub fn item_exit_server_system(
+    network_spawner: Res<NetworkSpawner>,
+    query: Query<&NetworkEntity, Or<(With<ItemPickup>, With<Chest>)>>,
+) {
+    for network_entity in query.iter() {
+        network_spawner.despawn(*network_entity);
+    }
+}
+
+/// The inventory of the local player, shown in the corner of the screen.
+pub struct InventoryDisplay {
+    items: Vec<String>,
+    text: Option<Entity>,
+}
+
+impl InventoryDisplay {
+    pub fn new() -> Self {
+        Self {
+            items: Vec::new(),
+            text: None,
+        }
+    }
+}
+
+pub fn item_client_system(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    network_entity_registry: Res<NetworkEntityRegistry>,
+    mut frames: ResMut<Assets<Frame>>,
+    items: Res<Assets<Item>>,
+    mut display: ResMut<InventoryDisplay>,
+    mut state: ResMut<State<GameState>>,
+    mut item_events: ResMut<NetworkEvents<ItemEvent>>,
+    mut inventory_events: ResMut<NetworkEvents<InventoryEvent>>,
+    mut player_query: Query<(&mut Player, &Children)>,
+    mut progress_bar_query: Query<&mut ProgressBar>,
+    mut text_query: Query<&mut Text>,
+) {
+    for (_sender, event) in item_events.take() {
+        match event {
+            ItemEvent::SetFrame(network_entity, frame) => {
+                let entity = network_entity_registry.get(&network_entity).unwrap();
+                let (mut player, children) = player_query.get_mut(entity).unwrap();
+
+                // the health, guard and stamina bars
+                let maxes = [frame.max_health, frame.max_guard, frame.max_stamina];
+
+                for (child, max) in children.iter().zip(&maxes) {
+                    progress_bar_query.get_mut(*child).unwrap().value_max = *max;
+                }
+
+                player.frame = frames.add(frame);
+
+                if *state.current() == GameState::Dungeon {
+                    state.overwrite_set(GameState::PreDuel).unwrap();
+                }
+            }
+        }
+    }
+
+    for (_sender, event) in inventory_events.take() {
+        match event {
+            InventoryEvent::SetItems(items) => display.items = items,
+        }
+
+        if display.text.is_none() {
+            let text = commands
+                .spawn_bundle(TextBundle {
+                    style: Style {
+                        position_type: PositionType::Absolute,
+                        position: Rect {
+                            top: Val::Px(8.0),
+                            right: Val::Px(8.0),
+                            ..Default::default()
+                        },
+                        ..Default::default()
+                    },
+                    text: Text::with_section(
+                        "",
+                        TextStyle {
+                            font: asset_server.load("fonts/DejaVuSansMono.ttf"),
+                            font_size: 18.0,
+                            color: Color::WHITE,
+                        },
+                        Default::default(),
+                    ),
+                    ..Default::default()
+                })
+                .id();
+
+            commands.spawn_bundle(UiCameraBundle::default());
+            display.text = Some(text);
+        }
+    }
+
+    // the item names are only known once the assets have loaded
+    if let Some(mut text) = display.text.and_then(|text| text_query.get_mut(text).ok()) {
+        text.sections[0].value = inventory_text(&display.items, &items);
+    }
+}
+
+fn inventory_text(inventory: &[String], items: &Assets<Item>) -> String {
+    let mut text = String::from("Items");
+
+    for path in inventory {
+        if let Some(item) = items.get(path.as_str()) {
+            text += &format!("\n{}: {}", item.name, item.description);
+        }
+    }
+
+    text
+}
+
+pub struct ItemLoader;
+
+crate::ron_loader!(ItemLoader, "itm" => Item);
+
+pub struct ItemPlugin;
+
+impl Plugin for ItemPlugin {
+    fn build(&self, app_builder: &mut AppBuilder) {
+        let is_server = app_builder
+            .world()
+            .get_resource::<NetworkSettings>()
+            .unwrap()
+            .is_server;
+
+        app_builder.add_asset::<Item>();
+        app_builder.add_asset_loader(ItemLoader);
+
+        app_builder.register_network_event::<InventoryEvent>();
+        app_builder.register_network_event::<ItemEvent>();
+        app_builder.register_network_spawnable::<ItemPickupSpawner>();
+        app_builder.register_network_spawnable::<ChestSpawner>();
+
+        if is_server {
+            app_builder.insert_resource(Loot::new());
+            app_builder.add_system(inventory_server_system.system());
+            app_builder.add_system_set(
+                SystemSet::on_update(GameState::Dungeon)
+                    // dropped loot is spawned at the end of the frame, so the dungeon
+                    // can't end before it's on the ground
+                    .with_system(loot_server_system.system().after("dungeon")),
+            );
+            app_builder.add_system_set(
+                SystemSet::on_exit(GameState::Dungeon)
+                    .with_system(item_exit_server_system.system()),
+            );
+            app_builder.add_system_set(
+                SystemSet::on_enter(GameState::PreDuel)
+                    .with_system(item_apply_server_system.system()),
+            );
+            app_builder.add_system_set(
+                SystemSet::on_update(GameState::PreDuel)
+                    .with_system(pre_duel_server_system.system()),
+            );
+        } else {
+            app_builder.insert_resource(InventoryDisplay::new());
+            app_builder.add_system(item_client_system.system());
+        }
+    }
+}
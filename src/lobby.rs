@@ -0,0 +1,354 @@
+//! The lobby of the server, which routes connecting clients to matches.
+//!
+//! Every match runs in its own [`App`] on its own thread, so matches share nothing
+//! but the listener and the admin console. Clients pick a match with the code in their
+//! [`ClientGreeting`], clients without a code join the open public match, and matches
+//! are cleaned up once they stop.
+
+use crate::client::ClientGreeting;
+use crate::config::*;
+use crate::console::HELP;
+use crate::game_settings::*;
+use crate::networking::*;
+use crate::server::{self, MatchClients};
+use bevy::prelude::*;
+use std::collections::HashMap;
+use std::io::BufRead;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::mpsc::{channel, SendError, Sender};
+use std::sync::{Arc, Mutex};
+
+/// The longest match code clients can pick.
+const MAX_CODE_LENGTH: usize = 32;
+
+enum LobbyEvent {
+    Connection(IncomingConnection),
+    Line(String),
+}
+
+/// A match running on its own thread.
+struct Match {
+    incoming: Sender<IncomingConnection>,
+    console: Sender<String>,
+    /// The number of clients sent to the match, the match gives back the slots of
+    /// clients leaving before it starts and closes it when it stops.
+    clients: Arc<AtomicUsize>,
+    /// The number of clients the match starts with.
+    slots: usize,
+    /// Whether clients without a code can join.
+    public: bool,
+    /// Set when the match has stopped.
+    finished: Arc<AtomicBool>,
+}
+
+pub struct Lobby {
+    config: ServerConfig,
+    game_settings: GameSettings,
+    bot: Option<String>,
+    matches: HashMap<String, Match>,
+    next_public: u32,
+    /// The match the console commands are sent to.
+    selected: Option<String>,
+}
+
+impl Lobby {
+    pub fn new(config: ServerConfig, game_settings: GameSettings, bot: Option<String>) -> Self {
+        Self {
+            config,
+            game_settings,
+            bot,
+            matches: HashMap::new(),
+            next_public: 1,
+            selected: None,
+        }
+    }
+
+    /// The number of clients a match starts with, bots and the training dummy take the other slots.
+    fn slots(&self) -> usize {
+        let bots = if self.bot.is_some() || self.game_settings.mode == GameMode::Training {
+            1
+        } else {
+            0
+        };
+
+        self.config.max_players() - bots
+    }
+
+    fn start_match(&mut self, code: String, public: bool) {
+        let (incoming, incoming_receiver) = channel();
+        let (console, console_receiver) = channel();
+        let clients = Arc::new(AtomicUsize::new(0));
+        let slots = self.slots();
+        let finished = Arc::new(AtomicBool::new(false));
+
+        let config = self.config.clone();
+        let game_settings = self.game_settings.clone();
+        let bot = self.bot.clone();
+        let match_code = code.clone();
+        let match_clients = clients.clone();
+        let match_finished = finished.clone();
+
+        std::thread::Builder::new()
+            .name(format!("match {}", code))
+            .spawn(move || {
+                server::run(
+                    match_code,
+                    config,
+                    game_settings,
+                    bot,
+                    incoming_receiver,
+                    match_clients,
+                    console_receiver,
+                );
+
+                match_finished.store(true, Ordering::Relaxed);
+            })
+            .unwrap();
+
+        info!("Started match '{}'", code);
+
+        self.matches.insert(
+            code,
+            Match {
+                incoming,
+                console,
+                clients,
+                slots,
+                public,
+                finished,
+            },
+        );
+    }
+
+    fn remove_finished(&mut self) {
+        let selected = &mut self.selected;
+
+        self.matches.retain(|code, game| {
+            let finished = game.finished.load(Ordering::Relaxed);
+
+            if finished {
+                info!("Match '{}' has finished", code);
+
+                if selected.as_ref() == Some(code) {
+                    *selected = None;
+                }
+            }
+
+            !finished
+        });
+    }
+
+    /// Sends a client to the match they picked, starting it if it isn't running.
+    fn route(&mut self, incoming: IncomingConnection) {
+        self.remove_finished();
+
+        let code = bincode::deserialize::<ClientGreeting>(&incoming.greeting.data)
+            .map(|greeting| greeting.match_code)
+            .unwrap_or_default();
+
+        if !is_valid_code(&code) {
+            warn!("A client picked the invalid match code '{}'", code);
+            return;
+        }
+
+        let public = code.is_empty();
+
+        let code = if public {
+            let open = self
+                .matches
+                .iter()
+                .find(|(_, game)| game.public && game.clients.load(Ordering::SeqCst) < game.slots);
+
+            match open {
+                Some((code, _)) => code.clone(),
+                None => {
+                    let code = format!("public-{}", self.next_public);
+                    self.next_public += 1;
+                    code
+                }
+            }
+        } else {
+            code
+        };
+
+        if !self.matches.contains_key(&code) {
+            if self.matches.len() >= self.config.max_matches {
+                warn!(
+                    "All {} matches are running, '{}' can't start",
+                    self.matches.len(),
+                    code
+                );
+                return;
+            }
+
+            self.start_match(code.clone(), public);
+        }
+
+        let game = self.matches.get_mut(&code).unwrap();
+        let slots = game.slots;
+
+        let joined = game
+            .clients
+            .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |clients| {
+                if clients < slots {
+                    Some(clients + 1)
+                } else {
+                    None
+                }
+            });
+
+        match joined {
+            Ok(_) => (),
+            // the match is stopping, it's replaced by a new one
+            Err(MatchClients::CLOSED) => {
+                self.matches.remove(&code);
+                self.route(incoming);
+                return;
+            }
+            Err(_) => {
+                warn!("Match '{}' is full", code);
+                return;
+            }
+        }
+
+        // the match stopped without the lobby noticing, it's replaced by a new one
+        if let Err(SendError(incoming)) = game.incoming.send(incoming) {
+            self.matches.remove(&code);
+            self.route(incoming);
+        }
+    }
+
+    fn command(&mut self, line: String) {
+        self.remove_finished();
+
+        let args = line.split_whitespace().collect::<Vec<_>>();
+
+        match args.as_slice() {
+            ["matches"] => self.list(),
+            ["select", code] => {
+                if self.matches.contains_key(*code) {
+                    self.selected = Some(code.to_string());
+                    println!("Sending commands to match '{}'", code);
+                } else {
+                    println!("there's no match '{}'", code);
+                }
+            }
+            ["mode", mode] => match mode.parse() {
+                Ok(mode) => self.set_mode(mode),
+                Err(e) => println!("{}", e),
+            },
+            ["help"] if self.matches.is_empty() => println!("{}", HELP),
+            _ => {
+                // with a single match there's nothing to pick between
+                let selected = match &self.selected {
+                    Some(code) => Some(code.clone()),
+                    None if self.matches.len() == 1 => self.matches.keys().next().cloned(),
+                    None => None,
+                };
+
+                match selected.and_then(|code| self.matches.get(&code)) {
+                    Some(game) => {
+                        // the match might have stopped since it was last checked
+                        if game.console.send(line).is_err() {
+                            println!("the match has stopped");
+                        }
+                    }
+                    None if self.matches.is_empty() => println!("no matches are running"),
+                    None => println!("select a match first, type 'matches' for a list of them"),
+                }
+            }
+        }
+    }
+
+    /// Changes the game mode of the matches started after this, running matches keep theirs.
+    fn set_mode(&mut self, mode: GameMode) {
+        // the training dummy only makes sense in training
+        if self.game_settings.mode == GameMode::Training {
+            println!("the game mode of a training server can't be changed");
+            return;
+        }
+
+        // the player count comes from the config
+        self.config.mode = mode;
+        self.game_settings.mode = mode;
+
+        info!("Game mode set to {:?}, used by new matches", mode);
+    }
+
+    fn list(&self) {
+        let mut codes = self.matches.keys().collect::<Vec<_>>();
+        codes.sort();
+
+        println!("{:<36}{:<10}{}", "code", "clients", "public");
+
+        for code in codes {
+            let game = &self.matches[code];
+            let clients = match game.clients.load(Ordering::SeqCst) {
+                MatchClients::CLOSED => String::from("closed"),
+                clients => format!("{}/{}", clients, game.slots),
+            };
+            let selected = if self.selected.as_ref() == Some(code) {
+                " (selected)"
+            } else {
+                ""
+            };
+
+            println!("{:<36}{:<10}{}{}", code, clients, game.public, selected);
+        }
+    }
+}
+
+/// Codes are used in file names, so only letters, numbers, '-' and '_' are allowed.
+fn is_valid_code(code: &str) -> bool {
+    code.len() <= MAX_CODE_LENGTH
+        && code
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+}
+
+/// Runs the server, every match is run with [`server::run`].
+pub fn run(config: ServerConfig, game_settings: GameSettings, bot: Option<String>) {
+    // the log plugin sets the global logger, so it's only added once for all the matches
+    App::build()
+        .insert_resource(config.log_settings())
+        .add_plugin(bevy::log::LogPlugin);
+
+    let (sender, receiver) = channel();
+    let connection_sender = Mutex::new(sender.clone());
+
+    let listening = listen(&config.address, move |incoming| {
+        // the lobby only stops with the process
+        let _ = connection_sender
+            .lock()
+            .unwrap()
+            .send(LobbyEvent::Connection(incoming));
+    });
+
+    if let Err(e) = listening {
+        error!("Failed to listen on '{}': '{}'", config.address, e);
+        std::process::exit(1);
+    }
+
+    info!("Listening on '{}'", config.address);
+
+    // stdin is read on a separate thread so reading doesn't block the lobby
+    std::thread::spawn(move || {
+        for line in std::io::stdin().lock().lines() {
+            let sent = line.map(|line| sender.send(LobbyEvent::Line(line)));
+
+            // stdin was closed, or the server stopped
+            if !matches!(sent, Ok(Ok(()))) {
+                break;
+            }
+        }
+    });
+
+    let mut lobby = Lobby::new(config, game_settings, bot);
+
+    for event in receiver {
+        match event {
+            LobbyEvent::Connection(incoming) => lobby.route(incoming),
+            LobbyEvent::Line(line) => lobby.command(line),
+        }
+    }
+}
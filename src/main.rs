@@ -2,21 +2,41 @@
 
 mod angle;
 mod animation;
+mod animation_event;
+mod animation_state;
+mod asset_check;
 mod attack;
+mod bot;
 mod camera;
 mod client;
+mod config;
+mod console;
+mod debug_render;
+mod dungeon;
+mod enemy;
 mod frame;
+mod frame_data;
 mod game_settings;
 mod game_state;
 mod helper;
 mod input;
+mod item;
+mod lobby;
+mod map;
+mod match_log;
 mod networking;
 mod player;
 mod progress_bar;
+mod round;
 mod server;
+mod simulation;
+mod status_effect;
+mod training;
 mod transform;
 
 use clap::Clap;
+use config::*;
+use game_settings::*;
 
 /// The options for command line arguments.
 /// Parsed with [`clap`].
@@ -24,34 +44,193 @@ use clap::Clap;
 struct Options {
     #[clap(short, long)]
     server: bool,
-    #[clap(short, long, default_value = "framefactorserver.ddns.net:35566")]
-    ip: String,
+    /// The address to connect to or listen on, overrides the config file.
+    #[clap(short, long)]
+    ip: Option<String>,
     #[clap(short, long)]
     local: bool,
+    /// The server config file, used when running a server.
+    #[clap(long, default_value = "server.ron")]
+    config: String,
+    /// The client config file.
+    #[clap(long, default_value = "client.ron")]
+    client_config: String,
+    /// The name other players see.
+    #[clap(long)]
+    name: Option<String>,
+    /// The frame to play as, if the server allows it.
+    #[clap(long)]
+    frame: Option<String>,
+    /// Joins the match with this code, players with the same code play together.
+    #[clap(long)]
+    match_code: Option<String>,
+    /// Parses and cross-checks every asset, then exits.
+    #[clap(long)]
+    check_assets: bool,
+    /// Prints the frame data of every move as a markdown or csv table, then exits.
+    #[clap(long, possible_values = &["markdown", "csv"])]
+    frame_data: Option<String>,
+    /// Simulates this many matches of every frame matchup between bots,
+    /// prints the results, then exits.
+    #[clap(long)]
+    simulate: Option<u32>,
+    /// Fills a player slot on the server with a bot, using the bot difficulty at this asset path.
+    /// Also the difficulty of the bots in simulated matches, normal if not set.
+    #[clap(long)]
+    bot: Option<String>,
+    /// The game mode the server hosts, or the simulation plays.
+    #[clap(long, possible_values = &["1v1", "2v2", "ffa", "koth"])]
+    mode: Option<GameMode>,
+    /// The match is best of this many rounds.
+    #[clap(long)]
+    rounds: Option<u32>,
+    /// Lets players hurt their teammates.
+    #[clap(long)]
+    friendly_fire: bool,
+    /// The path of the map duels are fought in.
+    #[clap(long)]
+    map: Option<String>,
+    /// The number of players the game starts with.
+    #[clap(long)]
+    max_players: Option<usize>,
+    #[clap(long, possible_values = &["error", "warn", "info", "debug", "trace"])]
+    log_level: Option<String>,
+    /// Runs a local server and a single client, playing against a training dummy.
+    #[clap(long)]
+    training: bool,
+    /// Plays through a procedurally generated dungeon before the duel.
+    #[clap(long)]
+    dungeon: bool,
+    /// The seed of the dungeon, random if not set.
+    #[clap(long)]
+    dungeon_seed: Option<u64>,
+    /// Generates the dungeon for this seed, prints it and checks that it's connected
+    /// and has a room for every player of the game mode, then exits.
+    #[clap(long)]
+    print_dungeon: Option<u64>,
 }
 
 fn main() {
     let opts = Options::parse();
 
-    if opts.local {
-        std::process::Command::new(std::env::args().next().unwrap())
-            .arg("-i")
-            .arg(opts.ip.clone())
-            .spawn()
-            .unwrap();
-
-        std::process::Command::new(std::env::args().next().unwrap())
-            .arg("-i")
-            .arg(opts.ip.clone())
-            .spawn()
-            .unwrap();
-
-        server::run(opts.ip);
-    } else {
-        if opts.server {
-            server::run(opts.ip);
-        } else {
-            client::run(opts.ip);
+    if opts.check_assets {
+        let errors = asset_check::check_assets("assets");
+        std::process::exit(if errors == 0 { 0 } else { 1 });
+    }
+
+    if let Some(format) = opts.frame_data {
+        let success = frame_data::print_frame_data("assets", &format);
+        std::process::exit(if success { 0 } else { 1 });
+    }
+
+    if let Some(matches) = opts.simulate {
+        let mode = opts.mode.unwrap_or(GameMode::OneVersusOne);
+        let difficulty = opts.bot.as_deref().unwrap_or("bots/normal.bot");
+        let success = simulation::simulate("assets", matches, mode, difficulty);
+        std::process::exit(if success { 0 } else { 1 });
+    }
+
+    if let Some(seed) = opts.print_dungeon {
+        let mode = opts.mode.unwrap_or(GameMode::OneVersusOne);
+        let success = dungeon::print_dungeon(seed, mode);
+        std::process::exit(if success { 0 } else { 1 });
+    }
+
+    if !(opts.server || opts.local || opts.training) {
+        let mut config = exit_on_error(load_config::<ClientConfig>(&opts.client_config));
+
+        if let Some(ip) = opts.ip {
+            config.address = ip;
+        }
+
+        if let Some(name) = opts.name {
+            config.name = name;
+        }
+
+        if let Some(frame) = opts.frame {
+            config.frame = frame;
+        }
+
+        if let Some(match_code) = opts.match_code {
+            config.match_code = match_code;
+        }
+
+        client::run(config);
+        return;
+    }
+
+    let mut config = exit_on_error(load_config::<ServerConfig>(&opts.config));
+
+    if let Some(ip) = opts.ip {
+        config.address = ip;
+    }
+
+    if let Some(mode) = opts.mode {
+        config.mode = mode;
+    }
+
+    if let Some(rounds) = opts.rounds {
+        config.rounds = rounds;
+    }
+
+    if opts.friendly_fire {
+        config.friendly_fire = true;
+    }
+
+    if let Some(map) = opts.map {
+        config.map = map;
+    }
+
+    if opts.max_players.is_some() {
+        config.max_players = opts.max_players;
+    }
+
+    if let Some(log_level) = opts.log_level {
+        config.log_level = log_level;
+    }
+
+    if opts.training {
+        config.mode = GameMode::Training;
+        config.max_players = None;
+    }
+
+    exit_on_error(config.validate());
+
+    let game_settings = GameSettings {
+        mode: config.mode,
+        rounds: config.rounds,
+        friendly_fire: config.friendly_fire,
+        map: config.map.clone(),
+        dungeon: opts.dungeon,
+        dungeon_seed: opts.dungeon_seed,
+    };
+
+    if opts.training || opts.local {
+        // a bot or the training dummy takes the place of one of the clients
+        let bots = if opts.training || opts.bot.is_some() { 1 } else { 0 };
+
+        for _ in 0..config.max_players() - bots {
+            std::process::Command::new(std::env::args().next().unwrap())
+                .arg("-i")
+                .arg(config.address.clone())
+                .arg("--client-config")
+                .arg(opts.client_config.clone())
+                .spawn()
+                .unwrap();
+        }
+    }
+
+    let bot = if opts.training { None } else { opts.bot };
+
+    lobby::run(config, game_settings, bot);
+}
+
+fn exit_on_error<T>(result: Result<T, String>) -> T {
+    match result {
+        Ok(value) => value,
+        Err(e) => {
+            eprintln!("{}", e);
+            std::process::exit(1);
         }
     }
 }
@@ -0,0 +1,196 @@
+//! The arenas duels are fought in, loaded from `.map` assets.
+//!
+//! The server creates the colliders and keeps players inside the bounds,
+//! the client draws the background and the walls.
+
+use crate::debug_render::polygon_mesh;
+use crate::game_settings::*;
+use crate::game_state::*;
+use crate::networking::*;
+use crate::player::*;
+use bevy::prelude::*;
+use heron::prelude::*;
+use std::collections::HashMap;
+
+/// The depth the background is drawn at, behind everything else.
+const BACKGROUND_Z: f32 = -0.1;
+/// The depth the walls are drawn at, above the background.
+const WALL_Z: f32 = -0.095;
+const WALL_COLOR: Color = Color::rgb(0.2, 0.18, 0.16);
+
+/// The area players can move in.
+#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct MapBounds {
+    pub min: Vec2,
+    pub max: Vec2,
+}
+
+impl MapBounds {
+    pub fn contains(&self, point: Vec2) -> bool {
+        point.cmpge(self.min).all() && point.cmple(self.max).all()
+    }
+
+    pub fn clamp(&self, point: Vec2) -> Vec2 {
+        point.max(self.min).min(self.max)
+    }
+}
+
+#[derive(Serialize, Deserialize, TypeUuid)]
+#[serde(deny_unknown_fields)]
+#[uuid = "6f1e2d8a-94c3-4b57-a0d6-3c8b7e5f2a14"]
+pub struct Map {
+    pub name: String,
+    /// The image drawn behind everything, centered on the origin.
+    pub background: String,
+    /// The size of a pixel of the background in world units.
+    pub background_scale: f32,
+    pub bounds: MapBounds,
+    /// Static walls, every collider is a convex polygon.
+    pub colliders: Vec<Vec<Vec2>>,
+    /// The spawn points of game modes, indexed by player slot, the teams are decided by
+    /// [`GameMode::team`]. Game modes without spawn points use [`GameMode::spawn_position`].
+    #[serde(default, deserialize_with = "crate::helper::unique_map")]
+    pub spawn_points: HashMap<GameMode, Vec<Vec2>>,
+}
+
+impl Map {
+    /// Where the player in `slot` spawns, modes without spawn points on the map use the defaults.
+    pub fn spawn_position(&self, mode: GameMode, slot: usize) -> Vec2 {
+        match self.spawn_points.get(&mode) {
+            Some(points) if !points.is_empty() => points[slot % points.len()],
+            _ => mode.spawn_position(slot),
+        }
+    }
+}
+
+pub struct MapLoader;
+
+crate::ron_loader!(MapLoader, "map" => Map);
+
+/// Marks the colliders, background and walls of a map, which are despawned when leaving it.
+pub struct MapEntity;
+
+pub fn map_start_server_system(
+    mut commands: Commands,
+    game_settings: Res<GameSettings>,
+    maps: Res<Assets<Map>>,
+) {
+    let map = maps
+        .get(&maps.get_handle(game_settings.map.as_str()))
+        .unwrap();
+
+    info!("Loading map '{}'", map.name);
+
+    for collider in &map.colliders {
+        commands
+            .spawn()
+            .insert(Transform::default())
+            .insert(GlobalTransform::default())
+            .insert(crate::helper::convex_hull(collider.clone()))
+            .insert(BodyType::Static)
+            .insert(MapEntity);
+    }
+}
+
+/// Keeps the players inside the bounds of the map.
+pub fn map_bounds_server_system(
+    game_settings: Res<GameSettings>,
+    maps: Res<Assets<Map>>,
+    mut query: Query<&mut Transform, With<Player>>,
+) {
+    let bounds = maps
+        .get(&maps.get_handle(game_settings.map.as_str()))
+        .unwrap()
+        .bounds;
+
+    for mut transform in query.iter_mut() {
+        let position = transform.translation.truncate();
+
+        if !bounds.contains(position) {
+            transform.translation = bounds.clamp(position).extend(transform.translation.z);
+        }
+    }
+}
+
+pub fn map_client_system(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    game_settings: Res<GameSettings>,
+    maps: Res<Assets<Map>>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<ColorMaterial>>,
+) {
+    let map = maps
+        .get(&maps.get_handle(game_settings.map.as_str()))
+        .unwrap();
+
+    commands
+        .spawn_bundle(SpriteBundle {
+            material: materials.add(asset_server.load(map.background.as_str()).into()),
+            transform: Transform {
+                translation: Vec3::new(0.0, 0.0, BACKGROUND_Z),
+                scale: Vec3::new(map.background_scale, map.background_scale, 1.0),
+                ..Default::default()
+            },
+            ..Default::default()
+        })
+        .insert(MapEntity);
+
+    let wall = materials.add(WALL_COLOR.into());
+
+    for collider in &map.colliders {
+        commands
+            .spawn_bundle(SpriteBundle {
+                sprite: Sprite::new(Vec2::ONE),
+                mesh: meshes.add(polygon_mesh(collider)),
+                material: wall.clone(),
+                transform: Transform::from_translation(Vec3::new(0.0, 0.0, WALL_Z)),
+                ..Default::default()
+            })
+            .insert(MapEntity);
+    }
+}
+
+pub fn map_exit_system(mut commands: Commands, query: Query<Entity, With<MapEntity>>) {
+    for entity in query.iter() {
+        commands.entity(entity).despawn();
+    }
+}
+
+pub struct MapPlugin;
+
+impl Plugin for MapPlugin {
+    fn build(&self, app_builder: &mut AppBuilder) {
+        let is_server = app_builder
+            .world()
+            .get_resource::<NetworkSettings>()
+            .unwrap()
+            .is_server;
+
+        app_builder.add_asset::<Map>();
+        app_builder.add_asset_loader(MapLoader);
+
+        // duels and training are both fought in the arena
+        for state in [GameState::Duel, GameState::Training].iter() {
+            app_builder.add_system_set(
+                SystemSet::on_exit(state.clone()).with_system(map_exit_system.system()),
+            );
+
+            if is_server {
+                app_builder.add_system_set(
+                    SystemSet::on_enter(state.clone())
+                        .with_system(map_start_server_system.system()),
+                );
+                app_builder.add_system_set(
+                    SystemSet::on_update(state.clone())
+                        .with_system(map_bounds_server_system.system()),
+                );
+            } else {
+                app_builder.add_system_set(
+                    SystemSet::on_enter(state.clone()).with_system(map_client_system.system()),
+                );
+            }
+        }
+    }
+}
@@ -0,0 +1,464 @@
+//! The match log, every match is written to `logs/` as JSON lines.
+//!
+//! Every line is an event with the tick it happened on, counted from the first
+//! event of the match. When the match ends the stats of every player are written
+//! to the log and sent to the clients for the score screen.
+
+use crate::attack::*;
+use crate::game_settings::*;
+use crate::game_state::*;
+use crate::networking::*;
+use crate::player::*;
+use crate::round::*;
+use crate::server::{MatchCode, Players};
+use bevy::prelude::*;
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+
+/// The folder match logs are written to.
+const LOG_DIR: &str = "logs";
+
+#[derive(Serialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum MatchLogEvent {
+    Connected {
+        actor: u32,
+        name: String,
+    },
+    Disconnected {
+        actor: u32,
+        name: String,
+    },
+    Spawned {
+        actor: u32,
+        name: String,
+        team: Team,
+        position: Vec2,
+    },
+    /// Written when a dungeon starts, so it can be generated again with `--print-dungeon`.
+    DungeonGenerated {
+        seed: u64,
+    },
+    AttackStarted {
+        attacker: u32,
+        attack: String,
+    },
+    Hit {
+        attacker: u32,
+        victim: u32,
+        attack: String,
+        damage: f32,
+        result: HitResult,
+    },
+    Stunned {
+        actor: u32,
+        frames: u32,
+    },
+    Died {
+        actor: u32,
+    },
+    RoundEnded {
+        round: u32,
+        winner: Option<Team>,
+    },
+    MatchEnded {
+        winner: Option<Team>,
+    },
+    Stats {
+        stats: Vec<PlayerSummary>,
+    },
+}
+
+#[derive(Serialize)]
+struct MatchLogLine<'a> {
+    tick: u32,
+    #[serde(flatten)]
+    event: &'a MatchLogEvent,
+}
+
+/// The stats of a player, counted while the match is played.
+#[derive(Default)]
+pub struct PlayerStats {
+    pub damage_dealt: f32,
+    pub damage_taken: f32,
+    pub hits_landed: u32,
+    pub attacks_started: u32,
+    /// The number of attacks that hit at least once.
+    pub attacks_landed: u32,
+    /// Whether the current attack has hit yet.
+    current_attack_landed: bool,
+    /// The number of times every attack was used.
+    pub attacks: HashMap<String, u32>,
+}
+
+impl PlayerStats {
+    /// The part of the attacks started that hit at least once.
+    pub fn accuracy(&self) -> f32 {
+        if self.attacks_started == 0 {
+            0.0
+        } else {
+            self.attacks_landed as f32 / self.attacks_started as f32
+        }
+    }
+
+    /// The most used attack, ties go to the first path alphabetically.
+    pub fn favourite_attack(&self) -> Option<String> {
+        self.attacks
+            .iter()
+            .max_by(|(a_path, a), (b_path, b)| a.cmp(b).then(b_path.cmp(a_path)))
+            .map(|(path, _)| path.clone())
+    }
+}
+
+/// The end of match stats of a player, as shown on the score screen.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct PlayerSummary {
+    pub name: String,
+    pub team: Team,
+    pub damage_dealt: f32,
+    pub damage_taken: f32,
+    pub hits_landed: u32,
+    pub accuracy: f32,
+    pub favourite_attack: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, TypeUuid)]
+#[uuid = "a3d41c6e-8f5b-4e27-9c10-5d7e2b4f8a69"]
+pub struct StatsEvent(pub Vec<PlayerSummary>);
+
+/// The log of the current match, the file is created when the first event is written.
+pub struct MatchLog {
+    /// The code of the match, so the logs of concurrent matches can be told apart.
+    code: String,
+    file: Option<BufWriter<File>>,
+    tick: u32,
+    stats: HashMap<ActorId, PlayerStats>,
+    /// The names of the connected clients, players leaving are removed from [`Players`].
+    names: HashMap<ActorId, String>,
+    /// Whether every player was stunned and dead last frame.
+    previous: HashMap<ActorId, (bool, bool)>,
+    /// Set when entering [`GameState::Ended`], the log is finished on the next update.
+    ended: bool,
+}
+
+impl MatchLog {
+    pub fn new(code: String) -> Self {
+        Self {
+            code,
+            file: None,
+            tick: 0,
+            stats: HashMap::new(),
+            names: HashMap::new(),
+            previous: HashMap::new(),
+            ended: false,
+        }
+    }
+
+    /// Writes an event to the log, failing to write doesn't stop the match.
+    pub fn write(&mut self, event: MatchLogEvent) {
+        if self.file.is_none() {
+            match Self::create_file(&self.code) {
+                Ok(file) => self.file = Some(file),
+                Err(e) => {
+                    warn!("Failed to create the match log: '{}'", e);
+                    return;
+                }
+            }
+        }
+
+        let line = MatchLogLine {
+            tick: self.tick,
+            event: &event,
+        };
+
+        let file = self.file.as_mut().unwrap();
+        let result = serde_json::to_writer(&mut *file, &line)
+            .map_err(std::io::Error::from)
+            .and_then(|_| file.write_all(b"\n"));
+
+        if let Err(e) = result {
+            warn!("Failed to write to the match log: '{}'", e);
+        }
+    }
+
+    fn create_file(code: &str) -> std::io::Result<BufWriter<File>> {
+        std::fs::create_dir_all(LOG_DIR)?;
+
+        let time = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs();
+
+        let path = format!("{}/match-{}-{}.jsonl", LOG_DIR, code, time);
+        info!("Writing the match log to '{}'", path);
+
+        Ok(BufWriter::new(File::create(path)?))
+    }
+
+    pub fn stats(&mut self, actor_id: ActorId) -> &mut PlayerStats {
+        self.stats.entry(actor_id).or_default()
+    }
+
+    /// Writes the stats and closes the file, the next event starts a new log.
+    pub fn finish(&mut self, summaries: Vec<PlayerSummary>) {
+        self.write(MatchLogEvent::Stats { stats: summaries });
+
+        if let Some(mut file) = self.file.take() {
+            if let Err(e) = file.flush() {
+                warn!("Failed to write to the match log: '{}'", e);
+            }
+        }
+
+        *self = Self::new(self.code.clone());
+    }
+}
+
+pub fn match_log_server_system(
+    players: Res<Players>,
+    mut match_log: ResMut<MatchLog>,
+    mut connection_events: EventReader<ConnectionEvent>,
+    mut attack_events: EventReader<AttackStartEvent>,
+    mut hit_events: EventReader<HitEvent>,
+    mut round_events: EventReader<RoundEvent>,
+    event_sender: Res<NetworkEventSender>,
+    spawned_query: Query<(&Player, &Transform), Added<Player>>,
+    query: Query<(&NetworkEntity, &Player)>,
+) {
+    for event in connection_events.iter() {
+        match event {
+            ConnectionEvent::Connected { id, .. } => {
+                let name = players.name(id);
+                match_log.names.insert(*id, name.clone());
+                match_log.write(MatchLogEvent::Connected { actor: id.0, name });
+            }
+            ConnectionEvent::Disconnected { id, .. } => {
+                let name = match match_log.names.get(id) {
+                    Some(name) => name.clone(),
+                    None => players.name(id),
+                };
+                match_log.write(MatchLogEvent::Disconnected { actor: id.0, name });
+            }
+        }
+    }
+
+    for (player, transform) in spawned_query.iter() {
+        match_log.write(MatchLogEvent::Spawned {
+            actor: player.actor_id.0,
+            name: players.name(&player.actor_id),
+            team: player.team,
+            position: transform.translation.truncate(),
+        });
+    }
+
+    let actors = query
+        .iter()
+        .map(|(network_entity, player)| (*network_entity, player.actor_id))
+        .collect::<HashMap<_, _>>();
+
+    for event in attack_events.iter() {
+        let attacker = match actors.get(&event.attacker) {
+            Some(attacker) => *attacker,
+            None => continue,
+        };
+
+        let stats = match_log.stats(attacker);
+        stats.attacks_started += 1;
+        stats.current_attack_landed = false;
+        *stats.attacks.entry(event.attack.clone()).or_insert(0) += 1;
+
+        match_log.write(MatchLogEvent::AttackStarted {
+            attacker: attacker.0,
+            attack: event.attack.clone(),
+        });
+    }
+
+    for hit in hit_events.iter() {
+        // the attacker might have been despawned before their projectile hit
+        let (attacker, victim) = match (actors.get(&hit.source), actors.get(&hit.target)) {
+            (Some(attacker), Some(victim)) => (*attacker, *victim),
+            _ => continue,
+        };
+
+        let landed = hit.result == HitResult::Hit || hit.result == HitResult::GuardBreak;
+
+        let stats = match_log.stats(attacker);
+        stats.damage_dealt += hit.damage;
+
+        if landed {
+            stats.hits_landed += 1;
+
+            // a projectile landing after the next attack started counts for that attack
+            if !stats.current_attack_landed {
+                stats.current_attack_landed = true;
+                stats.attacks_landed += 1;
+            }
+        }
+
+        match_log.stats(victim).damage_taken += hit.damage;
+
+        match_log.write(MatchLogEvent::Hit {
+            attacker: attacker.0,
+            victim: victim.0,
+            attack: hit.attack.clone(),
+            damage: hit.damage,
+            result: hit.result,
+        });
+    }
+
+    for (_, player) in query.iter() {
+        let stunned = player.stun.is_some();
+        let previous = match_log
+            .previous
+            .insert(player.actor_id, (stunned, player.dead))
+            .unwrap_or((false, false));
+
+        if stunned && !previous.0 {
+            match_log.write(MatchLogEvent::Stunned {
+                actor: player.actor_id.0,
+                frames: player.stun.unwrap(),
+            });
+        }
+
+        if player.dead && !previous.1 {
+            match_log.write(MatchLogEvent::Died {
+                actor: player.actor_id.0,
+            });
+        }
+    }
+
+    for event in round_events.iter() {
+        match event {
+            RoundEvent::RoundEnded { round, winner } => {
+                match_log.write(MatchLogEvent::RoundEnded {
+                    round: *round,
+                    winner: *winner,
+                })
+            }
+            RoundEvent::MatchEnded { winner, .. } => {
+                match_log.write(MatchLogEvent::MatchEnded { winner: *winner })
+            }
+            RoundEvent::RoundStarted(_) => (),
+        }
+    }
+
+    if match_log.ended {
+        let summaries = query
+            .iter()
+            .filter(|(_, player)| players.players.contains(&player.actor_id))
+            .map(|(_, player)| {
+                let stats = match_log.stats(player.actor_id);
+
+                PlayerSummary {
+                    name: players.name(&player.actor_id),
+                    team: player.team,
+                    damage_dealt: stats.damage_dealt,
+                    damage_taken: stats.damage_taken,
+                    hits_landed: stats.hits_landed,
+                    accuracy: stats.accuracy(),
+                    favourite_attack: stats.favourite_attack(),
+                }
+            })
+            .collect::<Vec<_>>();
+
+        event_sender.send(&StatsEvent(summaries.clone())).unwrap();
+        match_log.finish(summaries);
+        return;
+    }
+
+    // the tick only starts counting once the match has started logging
+    if match_log.file.is_some() {
+        match_log.tick += 1;
+    }
+}
+
+pub fn match_log_ended_server_system(mut match_log: ResMut<MatchLog>) {
+    match_log.ended = true;
+}
+
+/// The score screen shown when the match has ended.
+pub fn score_client_system(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    mut events: ResMut<NetworkEvents<StatsEvent>>,
+) {
+    for (_sender, StatsEvent(summaries)) in events.take() {
+        let mut lines = vec![format!(
+            "{:<20}{:<6}{:>8}{:>8}{:>6}{:>10}  {}",
+            "name", "team", "dealt", "taken", "hits", "accuracy", "favourite attack"
+        )];
+
+        for summary in &summaries {
+            // the file name is enough to tell the attacks of a frame apart
+            let favourite_attack = summary
+                .favourite_attack
+                .as_ref()
+                .and_then(|path| std::path::Path::new(path).file_stem())
+                .map_or(String::from("-"), |stem| {
+                    stem.to_string_lossy().into_owned()
+                });
+
+            lines.push(format!(
+                "{:<20}{:<6}{:>8.1}{:>8.1}{:>6}{:>9.0}%  {}",
+                summary.name,
+                summary.team.0,
+                summary.damage_dealt,
+                summary.damage_taken,
+                summary.hits_landed,
+                summary.accuracy * 100.0,
+                favourite_attack,
+            ));
+        }
+
+        commands.spawn_bundle(TextBundle {
+            style: Style {
+                position_type: PositionType::Absolute,
+                position: Rect {
+                    top: Val::Px(64.0),
+                    left: Val::Px(64.0),
+                    ..Default::default()
+                },
+                ..Default::default()
+            },
+            text: Text::with_section(
+                lines.join("\n"),
+                TextStyle {
+                    font: asset_server.load("fonts/DejaVuSansMono.ttf"),
+                    font_size: 20.0,
+                    color: Color::WHITE,
+                },
+                Default::default(),
+            ),
+            ..Default::default()
+        });
+
+        commands.spawn_bundle(UiCameraBundle::default());
+    }
+}
+
+pub struct MatchLogPlugin;
+
+impl Plugin for MatchLogPlugin {
+    fn build(&self, app_builder: &mut AppBuilder) {
+        let is_server = app_builder
+            .world()
+            .get_resource::<NetworkSettings>()
+            .unwrap()
+            .is_server;
+
+        app_builder.register_network_event::<StatsEvent>();
+
+        if is_server {
+            let code = app_builder.world().get_resource::<MatchCode>().unwrap().0.clone();
+
+            app_builder.insert_resource(MatchLog::new(code));
+            app_builder.add_system(match_log_server_system.system().after("connection"));
+            app_builder.add_system_set(
+                SystemSet::on_enter(GameState::Ended)
+                    .with_system(match_log_ended_server_system.system()),
+            );
+        } else {
+            app_builder.add_system(score_client_system.system());
+        }
+    }
+}
@@ -4,17 +4,20 @@ pub mod network_event;
 pub mod network_payload;
 pub mod network_resource;
 pub mod network_spawn;
+pub mod network_stats;
 
 pub use network_event::*;
 pub use network_payload::*;
 pub use network_resource::*;
 pub use network_spawn::*;
+pub use network_stats::*;
 
 pub use bevy::reflect::TypeUuid;
 pub use serde::{Deserialize, Serialize};
 
 use bevy::prelude::*;
 use std::net::TcpStream;
+use std::sync::{mpsc::Receiver, Mutex};
 
 #[derive(Debug, Hash, PartialEq, Eq, Clone, StageLabel)]
 pub enum NetworkStage {
@@ -27,21 +30,35 @@ pub struct NetworkSettings {
 
 pub struct NetworkPlugin {
     client_payload: Option<NetworkPayload>,
-    server_ip: String,
+    server_ip: Option<String>,
+    /// The clients connecting to a server, taken when the plugin is built.
+    incoming: Mutex<Option<Receiver<IncomingConnection>>>,
 }
 
 impl NetworkPlugin {
     pub fn client(ip: String, greeting: NetworkPayload) -> Self {
         Self {
             client_payload: Some(greeting),
-            server_ip: ip,
+            server_ip: Some(ip),
+            incoming: Mutex::new(None),
         }
     }
 
-    pub fn server(ip: String) -> Self {
+    /// A server connecting the clients received from [`listen`].
+    pub fn server(incoming: Receiver<IncomingConnection>) -> Self {
         Self {
             client_payload: None,
-            server_ip: ip,
+            server_ip: None,
+            incoming: Mutex::new(Some(incoming)),
+        }
+    }
+
+    /// A server that doesn't listen for connections, everything sent is dropped.
+    pub fn offline() -> Self {
+        Self {
+            client_payload: None,
+            server_ip: None,
+            incoming: Mutex::new(None),
         }
     }
 }
@@ -78,18 +95,26 @@ impl Plugin for NetworkPlugin {
             network_spawner_system.exclusive_system(),
         );
 
+        app_builder.register_network_event::<PingEvent>();
+
         if let Some(greeting) = &self.client_payload {
-            let stream = TcpStream::connect(&self.server_ip).unwrap();
+            let stream = TcpStream::connect(self.server_ip.as_ref().unwrap()).unwrap();
 
             app_builder.insert_resource(NetworkResource::client(stream, greeting).unwrap());
+            app_builder.add_system(ping_client_system.system());
         } else {
-            app_builder.insert_resource(ServerResource::new(&self.server_ip).unwrap());
             app_builder.insert_resource(NetworkResource::empty());
+            app_builder.insert_resource(NetworkStats::new());
+            app_builder.add_system(ping_server_system.system());
+
+            if let Some(incoming) = self.incoming.lock().unwrap().take() {
+                app_builder.insert_resource(ServerResource::new(incoming));
 
-            app_builder.add_system_to_stage(
-                bevy::app::CoreStage::PreUpdate,
-                network_server_system.system(),
-            );
+                app_builder.add_system_to_stage(
+                    bevy::app::CoreStage::PreUpdate,
+                    network_server_system.system(),
+                );
+            }
         }
     }
 }
@@ -33,8 +33,11 @@ pub fn network_event_sender_system(
     sender: Res<NetworkEventSender>,
     net: Res<NetworkResource>,
 ) {
-    for payload in sender.take() {
-        let connection_events = net.send(&payload);
+    for (receiver, payload) in sender.take() {
+        let connection_events = match receiver {
+            Some(receiver) => net.send_to(&receiver, &payload),
+            None => net.send(&payload),
+        };
 
         events.send_batch(connection_events.into_iter());
     }
@@ -57,13 +60,19 @@ impl<T> NetworkEvents<T> {
         self.events.push((sender, event));
     }
 
+    /// Iterates the events without taking them, for systems that only observe.
+    pub fn iter(&self) -> impl Iterator<Item = &(ActorId, T)> {
+        self.events.iter()
+    }
+
     pub fn take(&mut self) -> Vec<(ActorId, T)> {
         std::mem::replace(&mut self.events, Vec::new())
     }
 }
 
 pub struct NetworkEventSender {
-    payloads: Arc<Mutex<Vec<NetworkPayload>>>,
+    /// The payloads and who they're sent to, `None` sends to everyone.
+    payloads: Arc<Mutex<Vec<(Option<ActorId>, NetworkPayload)>>>,
 }
 
 impl NetworkEventSender {
@@ -73,11 +82,20 @@ impl NetworkEventSender {
         }
     }
 
-    pub fn take(&self) -> Vec<NetworkPayload> {
+    pub fn take(&self) -> Vec<(Option<ActorId>, NetworkPayload)> {
         std::mem::replace(&mut *self.payloads.lock().unwrap(), Vec::new())
     }
 
     pub fn send<T: NetworkEvent>(&self, event: &T) -> anyhow::Result<()> {
+        self.push(None, event)
+    }
+
+    /// Sends an event to a single actor, for things the other actors shouldn't know.
+    pub fn send_to<T: NetworkEvent>(&self, receiver: ActorId, event: &T) -> anyhow::Result<()> {
+        self.push(Some(receiver), event)
+    }
+
+    fn push<T: NetworkEvent>(&self, receiver: Option<ActorId>, event: &T) -> anyhow::Result<()> {
         let data = bincode::serialize(event)?;
 
         let payload = NetworkPayload {
@@ -87,7 +105,7 @@ impl NetworkEventSender {
 
         let mut payloads = self.payloads.lock().unwrap();
 
-        payloads.push(payload);
+        payloads.push((receiver, payload));
 
         Ok(())
     }
@@ -6,8 +6,17 @@ use std::{
     collections::HashMap,
     io::prelude::*,
     net::{TcpListener, TcpStream},
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        mpsc::Receiver,
+        Arc, Mutex,
+    },
+    time::Duration,
 };
 
+/// The time a client has to send their greeting after connecting.
+const GREETING_TIMEOUT: Duration = Duration::from_secs(5);
+
 /// An id given to each *actor* on the network, these include:
 /// * Server
 /// * Client
@@ -17,8 +26,8 @@ use std::{
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub struct ActorId(pub u32);
 
-/// When a client connects to the server, the server sends this greeting
-/// to the client with all the perdinent information.
+/// When a client connects to the server, the client sends a greeting first,
+/// and the server answers with this greeting with all the perdinent information.
 #[derive(Serialize, Deserialize)]
 pub struct ServerGreeting {
     client_id: ActorId,
@@ -36,9 +45,22 @@ pub enum ConnectionEvent {
     },
 }
 
+/// The amount of data sent and received over a [`Connection`].
+#[derive(Clone, Copy, Debug, Default)]
+pub struct ConnectionStats {
+    pub payloads_sent: u64,
+    pub payloads_received: u64,
+    pub bytes_sent: u64,
+    pub bytes_received: u64,
+}
+
 /// The connections stored in the [`NetworkResource`].
 pub struct Connection {
     stream: TcpStream,
+    payloads_sent: AtomicU64,
+    payloads_received: AtomicU64,
+    bytes_sent: AtomicU64,
+    bytes_received: AtomicU64,
 }
 
 impl Connection {
@@ -49,7 +71,27 @@ impl Connection {
         stream.set_nonblocking(true)?;
         stream.set_nodelay(true)?;
 
-        Ok(Self { stream })
+        Ok(Self {
+            stream,
+            payloads_sent: AtomicU64::new(0),
+            payloads_received: AtomicU64::new(0),
+            bytes_sent: AtomicU64::new(0),
+            bytes_received: AtomicU64::new(0),
+        })
+    }
+
+    pub fn stats(&self) -> ConnectionStats {
+        ConnectionStats {
+            payloads_sent: self.payloads_sent.load(Ordering::Relaxed),
+            payloads_received: self.payloads_received.load(Ordering::Relaxed),
+            bytes_sent: self.bytes_sent.load(Ordering::Relaxed),
+            bytes_received: self.bytes_received.load(Ordering::Relaxed),
+        }
+    }
+
+    /// The address of the other end of the connection.
+    pub fn peer_addr(&self) -> Option<std::net::SocketAddr> {
+        self.stream.peer_addr().ok()
     }
 
     pub fn recv_single_blocking(&self) -> bincode::Result<NetworkPayload> {
@@ -62,6 +104,26 @@ impl Connection {
         Ok(payload)
     }
 
+    fn send_greeting(&self, greeting: &ServerGreeting) -> bincode::Result<()> {
+        self.stream.set_nonblocking(false)?;
+
+        bincode::serialize_into(&self.stream, greeting)?;
+
+        self.stream.set_nonblocking(true)?;
+
+        Ok(())
+    }
+
+    fn recv_greeting(&self) -> bincode::Result<ServerGreeting> {
+        self.stream.set_nonblocking(false)?;
+
+        let greeting = bincode::deserialize_from(&self.stream)?;
+
+        self.stream.set_nonblocking(true)?;
+
+        Ok(greeting)
+    }
+
     pub fn recv_single(&self) -> bincode::Result<NetworkPayload> {
         let mut length = [0u8; 4];
         self.stream.peek(&mut length)?;
@@ -69,6 +131,9 @@ impl Connection {
         let mut buf = vec![0u8; length + 4];
         (&self.stream).read_exact(&mut buf)?;
 
+        self.payloads_received.fetch_add(1, Ordering::Relaxed);
+        self.bytes_received.fetch_add(buf.len() as u64, Ordering::Relaxed);
+
         bincode::deserialize(&buf[4..])
     }
 
@@ -102,58 +167,107 @@ impl Connection {
 
         (&self.stream).write_all(&msg)?;
 
+        self.payloads_sent.fetch_add(1, Ordering::Relaxed);
+        self.bytes_sent.fetch_add(msg.len() as u64, Ordering::Relaxed);
+
         Ok(())
     }
 }
 
+/// A client that has connected and sent their greeting, but hasn't been given an [`ActorId`].
+pub struct IncomingConnection {
+    pub connection: Connection,
+    pub greeting: NetworkPayload,
+}
+
+/// Binds to the given ip and accepts connections on a separate thread,
+/// `on_connection` is called with every client once they have sent their greeting.
+pub fn listen<F>(ip: &str, on_connection: F) -> anyhow::Result<()>
+where
+    F: Fn(IncomingConnection) + Send + Sync + 'static,
+{
+    let listener = TcpListener::bind(ip)?;
+    let on_connection = Arc::new(on_connection);
+
+    std::thread::spawn(move || {
+        for stream in listener.incoming() {
+            let stream = match stream {
+                Ok(stream) => stream,
+                Err(e) => {
+                    warn!("Failed to accept a connection: '{}'", e);
+                    continue;
+                }
+            };
+
+            let on_connection = on_connection.clone();
+
+            // a client taking its time with the greeting shouldn't hold up the others
+            std::thread::spawn(move || {
+                let incoming = stream
+                    .set_read_timeout(Some(GREETING_TIMEOUT))
+                    .map_err(anyhow::Error::from)
+                    .and_then(|_| Connection::new(stream))
+                    .and_then(|connection| {
+                        let greeting = connection.recv_single_blocking()?;
+                        connection.stream.set_read_timeout(None)?;
+
+                        Ok(IncomingConnection {
+                            connection,
+                            greeting,
+                        })
+                    });
+
+                match incoming {
+                    Ok(incoming) => on_connection(incoming),
+                    Err(e) => warn!("A client failed to greet: '{}'", e),
+                }
+            });
+        }
+    });
+
+    Ok(())
+}
+
 /// A resource used for storing server specific networking data.
 pub struct ServerResource {
-    listener: TcpListener,
+    incoming: Mutex<Receiver<IncomingConnection>>,
 }
 
 impl ServerResource {
-    /// Creates a new [`ServerResource`] and binds to the given ip.
-    pub fn new(ip: &String) -> anyhow::Result<Self> {
-        let listener = TcpListener::bind(ip)?;
-
-        listener.set_nonblocking(true)?;
-
-        Ok(Self { listener })
+    /// Creates a new [`ServerResource`], receiving the clients from [`listen`].
+    pub fn new(incoming: Receiver<IncomingConnection>) -> Self {
+        Self {
+            incoming: Mutex::new(incoming),
+        }
     }
 
-    /// Listens for connections and adds any connected clients to the [`NetworkResource`].
+    /// Adds any connected clients to the [`NetworkResource`].
     ///
     /// **Note** this will never block.
-    pub fn listen(&self, net: &mut NetworkResource) -> anyhow::Result<Vec<ConnectionEvent>> {
+    pub fn listen(&self, net: &mut NetworkResource) -> Vec<ConnectionEvent> {
         let mut events = Vec::new();
 
-        for stream in self.listener.incoming() {
-            match stream {
-                Ok(stream) => {
-                    let (id, greeting) = net.add_client(stream)?;
-
-                    events.push(ConnectionEvent::Connected { id, greeting });
-                }
-                Err(e) if e.kind() == std::io::ErrorKind::WouldBlock => {
-                    return Ok(events);
-                }
-                e => {
-                    e?;
-                }
+        for incoming in self.incoming.lock().unwrap().try_iter() {
+            match net.add_client(incoming.connection) {
+                Ok(id) => events.push(ConnectionEvent::Connected {
+                    id,
+                    greeting: incoming.greeting,
+                }),
+                Err(e) => warn!("Failed to greet a client: '{}'", e),
             }
         }
 
-        Ok(events)
+        events
     }
 }
 
-/// Listens for connecting clients and connects them.
+/// Connects the clients handed to the server.
 pub fn network_server_system(
     server_resource: Res<ServerResource>,
     mut net: ResMut<NetworkResource>,
     mut events: EventWriter<ConnectionEvent>,
 ) {
-    let connection_events = server_resource.listen(&mut *net).unwrap();
+    let connection_events = server_resource.listen(&mut *net);
 
     events.send_batch(connection_events.into_iter());
 }
@@ -181,15 +295,16 @@ impl NetworkResource {
     /// Creates a [`NetworkResource`] for a client from the given [`TcpStream`].
     ///
     /// **Note** this will block, since it listens for a [`ServerGreeting`].
-    pub fn client(mut stream: TcpStream, payload: &NetworkPayload) -> anyhow::Result<Self> {
+    pub fn client(stream: TcpStream, payload: &NetworkPayload) -> anyhow::Result<Self> {
         let mut network_resource = Self::empty();
 
-        let greeting: ServerGreeting = bincode::deserialize_from(&mut stream)?;
-
         let connection = Connection::new(stream)?;
 
+        // the server needs the greeting to know which match the client joins
         connection.send(payload)?;
 
+        let greeting = connection.recv_greeting()?;
+
         network_resource.insert_connection(greeting.server_id, connection);
 
         network_resource.server_id = greeting.server_id;
@@ -198,11 +313,8 @@ impl NetworkResource {
         Ok(network_resource)
     }
 
-    /// Takes a [`TcpStream`], sends a [`ServerGreeting`] and adds it as a connection.
-    pub fn add_client(
-        &mut self,
-        mut stream: TcpStream,
-    ) -> anyhow::Result<(ActorId, NetworkPayload)> {
+    /// Takes a [`Connection`], sends a [`ServerGreeting`] and adds it as a connection.
+    pub fn add_client(&mut self, connection: Connection) -> anyhow::Result<ActorId> {
         let id = self.next_id;
 
         let greeting = ServerGreeting {
@@ -210,16 +322,20 @@ impl NetworkResource {
             server_id: self.server_id,
         };
 
-        bincode::serialize_into(&mut stream, &greeting)?;
-
-        let connection = Connection::new(stream)?;
-        let payload = connection.recv_single_blocking()?;
+        connection.send_greeting(&greeting)?;
 
         self.connections.insert(id, connection);
 
         self.next_id.0 += 1;
 
-        Ok((id, payload))
+        Ok(id)
+    }
+
+    /// Reserves an [`ActorId`] for an actor without a connection, like a bot.
+    pub fn reserve_id(&mut self) -> ActorId {
+        let id = self.next_id;
+        self.next_id.0 += 1;
+        id
     }
 
     /// Inserts a connection.
@@ -270,4 +386,15 @@ impl NetworkResource {
 
         connection_events
     }
+
+    /// Sends a payload to a single actor, actors without a connection are ignored.
+    pub fn send_to(&self, actor_id: &ActorId, payload: &NetworkPayload) -> Vec<ConnectionEvent> {
+        match self.connections.get(actor_id).map(|connection| connection.send(payload)) {
+            Some(Err(e)) => vec![ConnectionEvent::Disconnected {
+                id: *actor_id,
+                error: e,
+            }],
+            _ => Vec::new(),
+        }
+    }
 }
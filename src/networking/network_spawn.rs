@@ -15,6 +15,12 @@ use std::{
 #[derive(Clone, Copy, Debug, Serialize, Deserialize, PartialEq, Eq, Hash)]
 pub struct NetworkEntity(u64);
 
+impl NetworkEntity {
+    pub fn id(&self) -> u64 {
+        self.0
+    }
+}
+
 pub struct NetworkEntityRegistry {
     entities: HashMap<NetworkEntity, Entity>,
     next_network_entity: NetworkEntity,
@@ -49,6 +55,10 @@ impl NetworkEntityRegistry {
         self.insert(network_entity, entity);
         network_entity
     }
+
+    pub fn remove(&mut self, network_entity: &NetworkEntity) -> Option<Entity> {
+        self.entities.remove(network_entity)
+    }
 }
 
 #[derive(Serialize, Deserialize)]
@@ -57,6 +67,13 @@ pub struct NetworkSpawnEvent<T> {
     spawnable: T,
 }
 
+/// Sent when a [`NetworkEntity`] is despawned with [`NetworkSpawner::despawn`].
+#[derive(Serialize, Deserialize, TypeUuid)]
+#[uuid = "e0f3a1d6-8c1b-4f5e-a3b8-6d0c2f9e7a41"]
+pub struct NetworkDespawnEvent {
+    network_entity: NetworkEntity,
+}
+
 pub trait NetworkSpawnable:
     Serialize + serde::de::DeserializeOwned + TypeUuid + 'static + Send + Sync
 {
@@ -82,6 +99,18 @@ fn spawn<T: NetworkSpawnable>(world: &mut World, payload: &NetworkPayload) {
         .insert(event.network_entity, entity);
 }
 
+fn despawn(world: &mut World, network_entity: NetworkEntity) {
+    let entity = world
+        .get_resource_mut::<NetworkEntityRegistry>()
+        .unwrap()
+        .remove(&network_entity);
+
+    // the entity might already have been despawned locally
+    if let Some(entity) = entity {
+        bevy::transform::hierarchy::despawn_with_children_recursive(world, entity);
+    }
+}
+
 pub struct NetworkSpawner {
     spawnables: HashMap<Uuid, Box<dyn Fn(&mut World, &NetworkPayload) + 'static + Send + Sync>>,
     spawns: Arc<Mutex<Vec<Box<dyn FnOnce(&mut World) -> NetworkPayload + 'static + Send + Sync>>>>,
@@ -126,6 +155,19 @@ impl NetworkSpawner {
         }));
     }
 
+    /// Despawns the entity on all actors.
+    pub fn despawn(&self, network_entity: NetworkEntity) {
+        let mut spawns = self.spawns.lock().unwrap();
+
+        spawns.push(Box::new(move |world| {
+            despawn(world, network_entity);
+
+            let event = NetworkDespawnEvent { network_entity };
+
+            NetworkPayload::new(&event, NetworkDespawnEvent::TYPE_UUID)
+        }));
+    }
+
     pub fn take_spawns(&self, world: &mut World) -> Vec<NetworkPayload> {
         self.spawns
             .lock()
@@ -158,6 +200,13 @@ pub fn network_spawner_system(world: &mut World) {
                     network_spawner.spawn_payload(world, &message.payload);
                 }
             }
+
+            for message in messages.take_messages(&NetworkDespawnEvent::TYPE_UUID) {
+                match bincode::deserialize::<NetworkDespawnEvent>(&message.payload.data) {
+                    Ok(event) => despawn(world, event.network_entity),
+                    Err(e) => error!("payload: {:?}, error: {}", message.payload, e),
+                }
+            }
         });
     });
 }
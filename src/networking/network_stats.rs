@@ -0,0 +1,85 @@
+//! Round trip times, measured by the server pinging every client.
+
+use super::*;
+use bevy::prelude::*;
+use std::{collections::HashMap, time::Instant};
+
+/// The number of frames between pings.
+const PING_INTERVAL: u32 = 48;
+/// The number of pings waiting for an answer, older ones are forgotten.
+const MAX_PENDING_PINGS: usize = 8;
+
+#[derive(Serialize, Deserialize, TypeUuid)]
+#[uuid = "c3a7e415-9d2b-4f60-8e1c-5b94d0f27a38"]
+pub enum PingEvent {
+    /// Sent by the server, the client answers with a pong with the same id.
+    Ping(u32),
+    Pong(u32),
+}
+
+/// The round trip times to the clients, only on the server.
+pub struct NetworkStats {
+    /// The last measured round trip time to every client, in seconds.
+    pub rtt: HashMap<ActorId, f32>,
+    /// When the pings waiting for an answer were sent.
+    pending: Vec<(u32, Instant)>,
+    next_ping: u32,
+    timer: u32,
+}
+
+impl NetworkStats {
+    pub fn new() -> Self {
+        Self {
+            rtt: HashMap::new(),
+            pending: Vec::new(),
+            next_ping: 0,
+            timer: 0,
+        }
+    }
+}
+
+pub fn ping_server_system(
+    mut stats: ResMut<NetworkStats>,
+    event_sender: Res<NetworkEventSender>,
+    mut events: ResMut<NetworkEvents<PingEvent>>,
+) {
+    for (sender, event) in events.take() {
+        if let PingEvent::Pong(id) = event {
+            if let Some((_, sent)) = stats.pending.iter().find(|(ping, _)| *ping == id) {
+                let rtt = sent.elapsed().as_secs_f32();
+
+                stats.rtt.insert(sender, rtt);
+            }
+        }
+    }
+
+    stats.timer += 1;
+
+    if stats.timer < PING_INTERVAL {
+        return;
+    }
+
+    stats.timer = 0;
+
+    let id = stats.next_ping;
+    stats.next_ping = stats.next_ping.wrapping_add(1);
+
+    if stats.pending.len() >= MAX_PENDING_PINGS {
+        stats.pending.remove(0);
+    }
+
+    stats.pending.push((id, Instant::now()));
+
+    event_sender.send(&PingEvent::Ping(id)).unwrap();
+}
+
+pub fn ping_client_system(
+    event_sender: Res<NetworkEventSender>,
+    mut events: ResMut<NetworkEvents<PingEvent>>,
+) {
+    for (_sender, event) in events.take() {
+        if let PingEvent::Ping(id) = event {
+            event_sender.send(&PingEvent::Pong(id)).unwrap();
+        }
+    }
+}
@@ -1,10 +1,14 @@
+use crate::angle::*;
 use crate::animation::*;
+use crate::animation_state::*;
 use crate::attack::*;
 use crate::camera::*;
 use crate::frame::*;
+use crate::game_settings::*;
 use crate::input::*;
 use crate::networking::*;
 use crate::progress_bar::*;
+use crate::status_effect::*;
 use crate::transform::*;
 use bevy::prelude::*;
 use heron::prelude::*;
@@ -14,34 +18,78 @@ use heron::prelude::*;
 pub enum PlayerEvent {
     SetHealth(NetworkEntity, f32),
     SetAttacking(NetworkEntity, bool),
+    /// Starts the attack with the path, the client can't start attacks on its own.
+    StartAttack(NetworkEntity, String),
     SetMovementVector(NetworkEntity, Vec2),
-    PlayAnimation(NetworkEntity, String),
+    SetBlocking(NetworkEntity, bool),
+    SetGuard(NetworkEntity, f32),
+    SetStamina(NetworkEntity, f32),
+    SetDead(NetworkEntity, bool),
+    SetAngle(NetworkEntity, Angle),
+    /// Sent when the aim direction changes, clients only show it.
+    SetAimDirection(NetworkEntity, Vec2),
+    /// Sent when a stun starts and ends, the client doesn't count it down.
+    SetStun(NetworkEntity, Option<u32>),
 }
 
-#[derive(Serialize, Deserialize, TypeUuid)]
+#[derive(Clone, Serialize, Deserialize, TypeUuid)]
 #[uuid = "1f4df47b-58da-477b-9921-0ac53cefd889"]
 pub enum PlayerInputEvent {
     // TODO: consider removing the NetworkEntity and just look up the senders player.
     SetMovement(NetworkEntity, Vec2),
     Attack(NetworkEntity, AttackType),
     SetAimDirection(NetworkEntity, Vec2),
+    SetBlock(NetworkEntity, bool),
+}
+
+/// The outcome of a [`Damage`] connecting with a [`Player`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum HitResult {
+    Hit,
+    Blocked,
+    GuardBreak,
+    Parried,
 }
 
 pub struct Player {
     pub frame: Handle<Frame>,
     pub movement_vector: Vec2,
     pub actor_id: ActorId,
+    pub team: Team,
     pub health: f32,
     pub update_health: bool,
     pub stun: Option<u32>,
+    pub update_stun: bool,
     pub attacking: bool,
+    /// The path of the last attack started.
+    pub attack: Option<String>,
+    /// Counts the attacks started, so the same attack twice in a row is played twice.
+    pub attacks_started: u32,
     pub aim_direction: Vec2,
+    /// The aim direction sent by the client, `aim_direction` turns towards it every frame.
+    pub target_aim_direction: Vec2,
+    /// True while the block button is held, blocking is decided by the server.
+    pub block_held: bool,
+    pub blocking: bool,
+    /// The number of frames the player has been blocking, used for the parry window.
+    pub block_time: u32,
+    pub guard: f32,
+    pub update_guard: bool,
+    pub stamina: f32,
+    pub update_stamina: bool,
+    pub dead: bool,
+    /// Set by the active status effects.
+    pub speed_multiplier: f32,
+    /// Set by the active status effects.
+    pub damage_taken_multiplier: f32,
+    /// The position the player is moved back to between rounds.
+    pub spawn_position: Vec2,
 }
 
 impl Player {
     #[inline(always)]
     pub fn damage(&mut self, damage: f32) {
-        self.health -= damage;
+        self.health -= damage * self.damage_taken_multiplier;
         self.update_health = true;
     }
 
@@ -51,18 +99,84 @@ impl Player {
             *stun = (*stun).max(new_stun);
         } else {
             self.stun = Some(new_stun);
+            self.update_stun = true;
         }
     }
 
+    /// Consumes `cost` stamina, returns false and consumes nothing if there isn't enough.
+    #[inline(always)]
+    pub fn use_stamina(&mut self, cost: f32) -> bool {
+        if self.stamina < cost {
+            return false;
+        }
+
+        self.stamina -= cost;
+        self.update_stamina = true;
+
+        true
+    }
+
     #[inline(always)]
     pub fn hit(&mut self, hit: &Damage) {
         self.damage(hit.damage);
         self.stun(hit.stun);
     }
+
+    /// Receives a hit, taking blocking, parrying and the guard meter into account.
+    ///
+    /// `from_front` should be true if the hit came from the direction the player is facing.
+    pub fn receive_hit(&mut self, hit: &Damage, from_front: bool, frame: &Frame) -> HitResult {
+        if !self.blocking || !from_front {
+            self.hit(hit);
+            return HitResult::Hit;
+        }
+
+        if self.block_time < frame.parry_window {
+            return HitResult::Parried;
+        }
+
+        self.guard -= hit.guard_damage;
+        self.update_guard = true;
+
+        if self.guard <= 0.0 {
+            // the guard is broken, the player has to press block again
+            self.guard = frame.max_guard;
+            self.block_held = false;
+            self.hit(hit);
+            self.stun(frame.guard_break_stun);
+
+            return HitResult::GuardBreak;
+        }
+
+        self.damage(hit.damage * frame.block_damage_multiplier);
+        self.stun(hit.block_stun);
+
+        HitResult::Blocked
+    }
+
+    /// Resets the player to the state it was spawned in, used between rounds.
+    pub fn reset(&mut self, frame: &Frame) {
+        self.health = frame.max_health;
+        self.update_health = true;
+        self.guard = frame.max_guard;
+        self.update_guard = true;
+        self.stamina = frame.max_stamina;
+        self.update_stamina = true;
+        self.stun = None;
+        self.update_stun = true;
+        self.attacking = false;
+        self.block_held = false;
+        self.dead = false;
+        self.speed_multiplier = 1.0;
+        self.damage_taken_multiplier = 1.0;
+    }
 }
 
+/// The factor velocity is multiplied by each frame while the player can't walk.
+const FRICTION: f32 = 0.85;
+
 pub struct PlayerResource {
-    player_entity: Option<Entity>,
+    pub player_entity: Option<Entity>,
 }
 
 pub fn player_server_system(
@@ -72,6 +186,7 @@ pub fn player_server_system(
     frames: Res<Assets<Frame>>,
     event_sender: Res<NetworkEventSender>,
     mut events: ResMut<NetworkEvents<PlayerInputEvent>>,
+    mut attack_events: EventWriter<AttackStartEvent>,
     mut query: Query<(
         &NetworkEntity,
         &mut Player,
@@ -82,10 +197,13 @@ pub fn player_server_system(
     )>,
 ) {
     // update players
-    for (network_entity, mut player, animator, _, _, mut velocity) in query.iter_mut() {
+    for (network_entity, mut player, mut animator, mut attack_controller, _, mut velocity) in
+        query.iter_mut()
+    {
         // remove stun if duration is over
         if player.stun == Some(0) {
             player.stun = None;
+            player.update_stun = true;
         }
 
         // if stun is active tick down
@@ -102,6 +220,79 @@ pub fn player_server_system(
                 .unwrap();
         }
 
+        // a player can only start blocking when free, but keeps blocking through blockstun
+        let blocking = player.block_held
+            && !player.attacking
+            && !player.dead
+            && (player.blocking || player.stun.is_none());
+
+        if blocking != player.blocking {
+            player.blocking = blocking;
+            player.block_time = 0;
+            event_sender
+                .send(&PlayerEvent::SetBlocking(*network_entity, blocking))
+                .unwrap();
+        }
+
+        if player.blocking {
+            player.block_time += 1;
+        }
+
+        // turn towards the aim of the client, limited by the turn speed of the attack
+        let target = player.target_aim_direction;
+        let aim_direction = match attack_controller.turn_speed {
+            Some((turn_speed, _)) => {
+                let current = player.aim_direction;
+                let angle = current.perp_dot(target).atan2(current.dot(target));
+                let angle = angle.max(-turn_speed).min(turn_speed);
+
+                (Quat::from_rotation_z(angle) * current.extend(0.0))
+                    .truncate()
+                    .normalize()
+            }
+            None => target,
+        };
+
+        if aim_direction != player.aim_direction {
+            player.aim_direction = aim_direction;
+            event_sender
+                .send(&PlayerEvent::SetAimDirection(
+                    *network_entity,
+                    aim_direction,
+                ))
+                .unwrap();
+        }
+
+        // face where the player aims while fighting or standing, and where they walk otherwise
+        let fighting = player.attacking || player.blocking;
+        let facing = if fighting || player.movement_vector == Vec2::ZERO {
+            -player.aim_direction
+        } else {
+            player.movement_vector
+        };
+        let angle = Angle::from_direction(facing);
+
+        if angle != animator.angle && !player.dead {
+            animator.set_angle(angle);
+            event_sender
+                .send(&PlayerEvent::SetAngle(*network_entity, angle))
+                .unwrap();
+        }
+
+        let frame = frames.get(&player.frame).unwrap();
+
+        // regenerate guard while not blocking
+        if !player.blocking && player.guard < frame.max_guard {
+            player.guard = (player.guard + frame.guard_regen).min(frame.max_guard);
+            player.update_guard = true;
+        }
+
+        // regenerate stamina while not attacking
+        if !player.attacking && player.stamina < frame.max_stamina {
+            player.stamina = (player.stamina + frame.stamina_regen).min(frame.max_stamina);
+            player.update_stamina = true;
+        }
+
         if player.update_health {
             player.update_health = false;
             let event = PlayerEvent::SetHealth(*network_entity, player.health);
@@ -109,15 +300,61 @@ pub fn player_server_system(
             event_sender.send(&event).unwrap();
         }
 
-        let frame = frames.get(&player.frame).unwrap();
+        if player.update_guard {
+            player.update_guard = false;
+            let event = PlayerEvent::SetGuard(*network_entity, player.guard);
+
+            event_sender.send(&event).unwrap();
+        }
+
+        if player.update_stamina {
+            player.update_stamina = false;
+            let event = PlayerEvent::SetStamina(*network_entity, player.stamina);
+
+            event_sender.send(&event).unwrap();
+        }
 
-        if player.stun.is_none() {
+        if player.update_stun {
+            player.update_stun = false;
+            let event = PlayerEvent::SetStun(*network_entity, player.stun);
+
+            event_sender.send(&event).unwrap();
+        }
+
+        if let Some((_, frames)) = &mut attack_controller.turn_speed {
+            if *frames == 0 {
+                attack_controller.turn_speed = None;
+            } else {
+                *frames -= 1;
+            }
+        }
+
+        let movement_locked = attack_controller
+            .attack
+            .as_ref()
+            .and_then(|attack| attacks.get(attack))
+            .map_or(false, |attack| attack.lock_movement);
+
+        if player.dead {
+            velocity.linear = Vec3::ZERO;
+        } else if let Some((attack_velocity, frames)) = &mut attack_controller.velocity {
+            velocity.linear = attack_velocity.extend(0.0);
+
+            if *frames == 0 {
+                attack_controller.velocity = None;
+            } else {
+                *frames -= 1;
+            }
+        } else if player.stun.is_some() || movement_locked {
+            // let push back and impulses slide out
+            velocity.linear *= FRICTION;
+        } else {
             let v = if player.movement_vector.length() == 0.0 {
                 Vec2::ZERO
             } else {
-                player.movement_vector.normalize() * frame.walking_speed
+                player.movement_vector.normalize() * frame.walking_speed * player.speed_multiplier
             };
-    
+
             velocity.linear = v.extend(0.0);
         }
     }
@@ -149,30 +386,38 @@ pub fn player_server_system(
 
             PlayerInputEvent::Attack(network_entity, attack_type) => {
                 let entity = network_entity_registry.get(&network_entity).unwrap();
-                let (network_entity, mut player, mut animator, mut attack_controller, _, _) =
+                let (network_entity, mut player, _, mut attack_controller, _, _) =
                     query.get_mut(entity).unwrap();
 
-                if player.attacking || player.actor_id != sender {
+                if player.attacking
+                    || player.dead
+                    || player.stun.is_some()
+                    || player.actor_id != sender
+                {
                     continue;
                 }
 
                 let frame = frames.get(&player.frame).unwrap();
-                let attack = frame.get_attack(&attack_type);
-                let attack_handle = attacks.get_handle(attack.as_str());
+                let attack_path = frame.get_attack(&attack_type).clone();
+                let attack_handle = attacks.get_handle(attack_path.as_str());
                 let attack = attacks.get(&attack_handle).unwrap();
 
-                attack_controller.attack(attack_handle);
-                animator.play(attack.animation.clone());
+                if !player.use_stamina(attack.stamina_cost) {
+                    continue;
+                }
+
+                attack_controller.attack(attack_path.clone(), attack_handle);
+                attack_events.send(AttackStartEvent {
+                    attacker: *network_entity,
+                    attack: attack_path.clone(),
+                });
 
+                // the animation state machine plays the attack
                 player.attacking = true;
+                player.attack = Some(attack_path.clone());
+                player.attacks_started += 1;
                 event_sender
-                    .send(&PlayerEvent::SetAttacking(*network_entity, true))
-                    .unwrap();
-                event_sender
-                    .send(&PlayerEvent::PlayAnimation(
-                        *network_entity,
-                        attack.animation.clone(),
-                    ))
+                    .send(&PlayerEvent::StartAttack(*network_entity, attack_path))
                     .unwrap();
             }
 
@@ -184,12 +429,48 @@ pub fn player_server_system(
                     continue;
                 }
 
-                player.aim_direction = aim_direction;
+                player.target_aim_direction = aim_direction;
+            }
+
+            PlayerInputEvent::SetBlock(network_entity, block_held) => {
+                let entity = network_entity_registry.get(&network_entity).unwrap();
+                let (_, mut player, _, _, _, _) = query.get_mut(entity).unwrap();
+
+                if player.actor_id != sender {
+                    continue;
+                }
+
+                player.block_held = block_held;
             }
         }
     }
 }
 
+/// Kills players whose health has reached zero, disabling their input and collisions.
+pub fn player_death_server_system(
+    mut commands: Commands,
+    event_sender: Res<NetworkEventSender>,
+    mut query: Query<(Entity, &NetworkEntity, &mut Player, &mut AttackController)>,
+) {
+    for (entity, network_entity, mut player, mut attack_controller) in query.iter_mut() {
+        if player.dead || player.health > 0.0 {
+            continue;
+        }
+
+        info!("Player '{:?}' died", player.actor_id);
+
+        player.dead = true;
+        player.attacking = false;
+        attack_controller.stop();
+
+        commands.entity(entity).remove::<Body>();
+
+        event_sender
+            .send(&PlayerEvent::SetDead(*network_entity, true))
+            .unwrap();
+    }
+}
+
 pub fn player_input_system(
     input_handle: Res<Handle<InputSettings>>,
     input_settings: Res<Assets<InputSettings>>,
@@ -259,15 +540,28 @@ pub fn player_input_system(
                 ))
                 .unwrap();
         }
+
+        // blocking
+
+        if input.block.just_pressed(&input_ctx) {
+            event_sender
+                .send(&PlayerInputEvent::SetBlock(*network_entity, true))
+                .unwrap();
+        }
+
+        if input.block.just_released(&input_ctx) {
+            event_sender
+                .send(&PlayerInputEvent::SetBlock(*network_entity, false))
+                .unwrap();
+        }
     }
 }
 
 pub fn player_client_system(
     mut events: ResMut<NetworkEvents<PlayerEvent>>,
     network_entity_registry: Res<NetworkEntityRegistry>,
-    frames: Res<Assets<Frame>>,
     mut player_query: Query<(&mut Player, &Children, &mut Animator)>,
-    mut health_bar_query: Query<&mut ProgressBar>,
+    mut progress_bar_query: Query<&mut ProgressBar>,
 ) {
     for (_sender, event) in events.take() {
         match event {
@@ -275,7 +569,7 @@ pub fn player_client_system(
                 let entity = network_entity_registry.get(&network_entity).unwrap();
 
                 let (mut player, children, _) = player_query.get_mut(entity).unwrap();
-                let mut health_bar = health_bar_query.get_mut(children[0]).unwrap();
+                let mut health_bar = progress_bar_query.get_mut(children[0]).unwrap();
 
                 info!("health set!");
 
@@ -296,21 +590,68 @@ pub fn player_client_system(
 
                 player.attacking = attacking;
             }
-            PlayerEvent::PlayAnimation(network_entity, animation) => {
+            PlayerEvent::StartAttack(network_entity, attack) => {
+                let entity = network_entity_registry.get(&network_entity).unwrap();
+
+                let (mut player, _, _) = player_query.get_mut(entity).unwrap();
+
+                player.attacking = true;
+                player.attack = Some(attack);
+                player.attacks_started += 1;
+            }
+            PlayerEvent::SetBlocking(network_entity, blocking) => {
+                let entity = network_entity_registry.get(&network_entity).unwrap();
+
+                let (mut player, _, _) = player_query.get_mut(entity).unwrap();
+
+                player.blocking = blocking;
+            }
+            PlayerEvent::SetGuard(network_entity, value) => {
+                let entity = network_entity_registry.get(&network_entity).unwrap();
+
+                let (mut player, children, _) = player_query.get_mut(entity).unwrap();
+                let mut guard_bar = progress_bar_query.get_mut(children[1]).unwrap();
+
+                player.guard = value;
+                guard_bar.value = player.guard;
+            }
+            PlayerEvent::SetStamina(network_entity, value) => {
+                let entity = network_entity_registry.get(&network_entity).unwrap();
+
+                let (mut player, children, _) = player_query.get_mut(entity).unwrap();
+                let mut stamina_bar = progress_bar_query.get_mut(children[2]).unwrap();
+
+                player.stamina = value;
+                stamina_bar.value = player.stamina;
+            }
+            PlayerEvent::SetDead(network_entity, dead) => {
+                let entity = network_entity_registry.get(&network_entity).unwrap();
+
+                let (mut player, _, _) = player_query.get_mut(entity).unwrap();
+
+                player.dead = dead;
+            }
+            PlayerEvent::SetAngle(network_entity, angle) => {
                 let entity = network_entity_registry.get(&network_entity).unwrap();
 
                 let (_, _, mut animator) = player_query.get_mut(entity).unwrap();
 
-                animator.play(animation);
+                animator.set_angle(angle);
             }
-        }
-    }
+            PlayerEvent::SetAimDirection(network_entity, aim_direction) => {
+                let entity = network_entity_registry.get(&network_entity).unwrap();
 
-    for (player, _, mut animator) in player_query.iter_mut() {
-        let frame = frames.get(&player.frame).unwrap();
+                let (mut player, _, _) = player_query.get_mut(entity).unwrap();
+
+                player.aim_direction = aim_direction;
+            }
+            PlayerEvent::SetStun(network_entity, stun) => {
+                let entity = network_entity_registry.get(&network_entity).unwrap();
 
-        if !player.attacking && player.movement_vector.length() == 0.0 {
-            animator.set_playing(frame.idle_animation.clone(), true);
+                let (mut player, _, _) = player_query.get_mut(entity).unwrap();
+
+                player.stun = stun;
+            }
         }
     }
 }
@@ -320,6 +661,7 @@ pub fn player_client_system(
 pub struct PlayerSpawner {
     pub frame: String,
     pub player_id: ActorId,
+    pub team: Team,
     pub position: Vec2,
 }
 
@@ -332,21 +674,36 @@ impl NetworkSpawnable for PlayerSpawner {
         let frame = frames.get(&frame_handle).unwrap();
 
         let max_health = frame.max_health;
+        let max_guard = frame.max_guard;
+        let max_stamina = frame.max_stamina;
 
         let player = Player {
             frame: frame_handle,
             movement_vector: Vec2::ZERO,
             actor_id: self.player_id,
+            team: self.team,
             health: max_health,
             update_health: false,
             stun: None,
+            update_stun: false,
             attacking: false,
+            attack: None,
+            attacks_started: 0,
             aim_direction: Vec2::new(1.0, 0.0),
+            target_aim_direction: Vec2::new(1.0, 0.0),
+            block_held: false,
+            blocking: false,
+            block_time: 0,
+            guard: max_guard,
+            update_guard: false,
+            stamina: max_stamina,
+            update_stamina: false,
+            dead: false,
+            speed_multiplier: 1.0,
+            damage_taken_multiplier: 1.0,
+            spawn_position: self.position,
         };
 
-        let mut animator = Animator::new();
-        animator.play(frame.idle_animation.clone());
-
         let body = Body::Sphere {
             radius: frame.collider_radius,
         };
@@ -361,24 +718,29 @@ impl NetworkSpawnable for PlayerSpawner {
                 .insert(player)
                 .insert(body)
                 .insert(Velocity::from_linear(Vec3::ZERO))
-                .insert(animator)
+                .insert(Animator::new())
+                .insert(AnimationStateController::new())
                 .insert(AttackController::new())
+                .insert(StatusEffects::new())
                 .insert(RotationConstraints::lock())
                 .id()
         } else {
-            let progress_bar_material = world
+            let progress_bar_materials = world
                 .get_resource::<Assets<ProgressBarMaterial>>()
-                .unwrap()
-                .get_handle("misc/health_bar.pb");
+                .unwrap();
+            let health_bar_material = progress_bar_materials.get_handle("misc/health_bar.pb");
+            let guard_bar_material = progress_bar_materials.get_handle("misc/guard_bar.pb");
+            let stamina_bar_material = progress_bar_materials.get_handle("misc/stamina_bar.pb");
 
             let entity = world
                 .spawn()
                 .insert_bundle(AnimatorBundle {
-                    animator,
                     transform,
                     ..Default::default()
                 })
                 .insert(player)
+                .insert(AnimationStateController::new())
+                .insert(StatusEffectIcons { icons: Vec::new() })
                 .insert(ZSort)
                 .with_children(|world| {
                     world.spawn_bundle(ProgressBarBundle {
@@ -386,10 +748,28 @@ impl NetworkSpawnable for PlayerSpawner {
                             value: max_health,
                             value_max: max_health,
                         },
-                        material: progress_bar_material,
+                        material: health_bar_material,
                         transform: Transform::from_translation(Vec3::new(0.0, 90.0, 0.0)),
                         ..Default::default()
                     });
+                    world.spawn_bundle(ProgressBarBundle {
+                        progress_bar: ProgressBar {
+                            value: max_guard,
+                            value_max: max_guard,
+                        },
+                        material: guard_bar_material,
+                        transform: Transform::from_translation(Vec3::new(0.0, 62.0, 0.0)),
+                        ..Default::default()
+                    });
+                    world.spawn_bundle(ProgressBarBundle {
+                        progress_bar: ProgressBar {
+                            value: max_stamina,
+                            value_max: max_stamina,
+                        },
+                        material: stamina_bar_material,
+                        transform: Transform::from_translation(Vec3::new(0.0, 74.0, 0.0)),
+                        ..Default::default()
+                    });
                 })
                 .id();
 
@@ -420,10 +800,11 @@ impl Plugin for PlayerPlugin {
         app_builder.register_network_spawnable::<PlayerSpawner>();
 
         if is_server {
-            app_builder.add_system(player_server_system.system());
+            app_builder.add_system(player_server_system.system().label("player"));
+            app_builder.add_system(player_death_server_system.system());
         } else {
             app_builder.add_system(player_input_system.system());
-            app_builder.add_system(player_client_system.system());
+            app_builder.add_system(player_client_system.system().label("player"));
 
             app_builder.insert_resource(PlayerResource {
                 player_entity: None,
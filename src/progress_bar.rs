@@ -20,6 +20,7 @@ pub struct ProgressBar {
 }
 
 #[derive(Serialize, Deserialize, RenderResources, TypeUuid, Default)]
+#[serde(deny_unknown_fields)]
 #[uuid = "14452f74-4e78-4aae-a737-76f30962be5f"]
 pub struct ProgressBarMaterial {
     pub size: Vec2,
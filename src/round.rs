@@ -0,0 +1,307 @@
+use crate::animation_state::*;
+use crate::attack::*;
+use crate::frame::*;
+use crate::game_settings::*;
+use crate::game_state::*;
+use crate::networking::*;
+use crate::player::*;
+use crate::server::TICK_RATE;
+use crate::status_effect::*;
+use bevy::prelude::*;
+use heron::prelude::*;
+use std::collections::{HashMap, HashSet};
+
+/// The number of frames between a round ending and the next one starting.
+const ROUND_RESET_DELAY: u32 = TICK_RATE as u32 * 2;
+/// The radius of the hill in the middle of the arena, in [`GameMode::KingOfTheHill`].
+pub const HILL_RADIUS: f32 = 48.0;
+/// The number of frames a team has to hold the hill alone to win the round.
+const HILL_TIME: u32 = TICK_RATE as u32 * 10;
+/// The depth the hill is drawn at, behind the players.
+const HILL_Z: f32 = -0.09;
+
+/// Sent to the clients, and as an event on the server.
+#[derive(Clone, Serialize, Deserialize, TypeUuid)]
+#[uuid = "5b0e7a4c-3f0d-4c84-9d4b-2f6a1c8e9b13"]
+pub enum RoundEvent {
+    RoundStarted(u32),
+    RoundEnded {
+        round: u32,
+        winner: Option<Team>,
+    },
+    MatchEnded {
+        winner: Option<Team>,
+        wins: Vec<(Team, u32)>,
+    },
+}
+
+/// Keeps track of the rounds played in the current match.
+pub struct Rounds {
+    pub round: u32,
+    pub wins: HashMap<Team, u32>,
+    /// The number of frames every team has held the hill alone this round.
+    pub hill: HashMap<Team, u32>,
+    /// Counts down to the start of the next round, when a round has ended.
+    pub reset_timer: Option<u32>,
+}
+
+impl Rounds {
+    pub fn new() -> Self {
+        Self {
+            round: 1,
+            wins: HashMap::new(),
+            hill: HashMap::new(),
+            reset_timer: None,
+        }
+    }
+
+    pub fn wins(&self, team: &Team) -> u32 {
+        self.wins.get(team).cloned().unwrap_or(0)
+    }
+}
+
+/// The components changed when resetting players, see [`reset_players`].
+pub type ResetQuery<'a> = (
+    Entity,
+    &'a NetworkEntity,
+    &'a mut Player,
+    &'a mut Transform,
+    &'a mut Velocity,
+    &'a mut AnimationStateController,
+    &'a mut AttackController,
+    &'a mut StatusEffects,
+);
+
+/// Moves all players back to where they spawned and resets their state.
+pub fn reset_players(
+    commands: &mut Commands,
+    frames: &Assets<Frame>,
+    event_sender: &NetworkEventSender,
+    query: &mut Query<ResetQuery>,
+) {
+    for (
+        entity,
+        network_entity,
+        mut player,
+        mut transform,
+        mut velocity,
+        mut animation_state,
+        mut attack_controller,
+        mut active_effects,
+    ) in query.iter_mut()
+    {
+        let frame = frames.get(&player.frame).unwrap();
+
+        player.reset(frame);
+        active_effects.clear();
+        transform.translation = player.spawn_position.extend(0.0);
+        velocity.linear = Vec3::ZERO;
+        attack_controller.stop();
+        animation_state.reset();
+
+        // collisions are disabled when a player dies
+        commands.entity(entity).insert(Body::Sphere {
+            radius: frame.collider_radius,
+        });
+
+        event_sender
+            .send(&PlayerEvent::SetDead(*network_entity, false))
+            .unwrap();
+        event_sender
+            .send(&PlayerEvent::SetAttacking(*network_entity, false))
+            .unwrap();
+    }
+}
+
+pub fn round_start_server_system(rounds: Res<Rounds>, event_sender: Res<NetworkEventSender>) {
+    event_sender
+        .send(&RoundEvent::RoundStarted(rounds.round))
+        .unwrap();
+}
+
+pub fn round_server_system(
+    mut commands: Commands,
+    game_settings: Res<GameSettings>,
+    frames: Res<Assets<Frame>>,
+    event_sender: Res<NetworkEventSender>,
+    mut rounds: ResMut<Rounds>,
+    mut state: ResMut<State<GameState>>,
+    mut round_events: EventWriter<RoundEvent>,
+    mut query: Query<ResetQuery>,
+) {
+    // wait for the next round to start
+    if let Some(timer) = &mut rounds.reset_timer {
+        if *timer > 0 {
+            *timer -= 1;
+            return;
+        }
+
+        rounds.reset_timer = None;
+        rounds.round += 1;
+        rounds.hill.clear();
+
+        reset_players(&mut commands, &frames, &event_sender, &mut query);
+
+        info!("Starting round {}", rounds.round);
+
+        event_sender
+            .send(&RoundEvent::RoundStarted(rounds.round))
+            .unwrap();
+
+        return;
+    }
+
+    let mut players = 0;
+    let mut alive = HashSet::new();
+    let mut on_hill = HashSet::new();
+
+    for (_, _, player, transform, _, _, _, _) in query.iter_mut() {
+        players += 1;
+
+        if player.dead {
+            continue;
+        }
+
+        alive.insert(player.team);
+
+        if transform.translation.truncate().length() <= HILL_RADIUS {
+            on_hill.insert(player.team);
+        }
+    }
+
+    if players < 2 {
+        return;
+    }
+
+    // the hill only counts while a single team is on it
+    let hill_winner = match on_hill.iter().next() {
+        Some(team) if game_settings.mode == GameMode::KingOfTheHill && on_hill.len() == 1 => {
+            let time = rounds.hill.entry(*team).or_insert(0);
+            *time += 1;
+
+            if *time >= HILL_TIME {
+                Some(*team)
+            } else {
+                None
+            }
+        }
+        _ => None,
+    };
+
+    // if everyone died at the same time the round is a draw
+    let winner = match hill_winner {
+        Some(team) => Some(team),
+        None if alive.len() <= 1 => alive.into_iter().next(),
+        None => return,
+    };
+
+    if let Some(winner) = winner {
+        *rounds.wins.entry(winner).or_insert(0) += 1;
+    }
+
+    info!("Round {} ended, winner: '{:?}'", rounds.round, winner);
+
+    let event = RoundEvent::RoundEnded {
+        round: rounds.round,
+        winner,
+    };
+
+    event_sender.send(&event).unwrap();
+    round_events.send(event);
+
+    let wins_needed = game_settings.rounds / 2 + 1;
+
+    if winner.map_or(false, |winner| rounds.wins(&winner) >= wins_needed) {
+        info!("Match ended, winner: '{:?}'", winner);
+
+        let event = RoundEvent::MatchEnded {
+            winner,
+            wins: rounds
+                .wins
+                .iter()
+                .map(|(team, wins)| (*team, *wins))
+                .collect(),
+        };
+
+        event_sender.send(&event).unwrap();
+        round_events.send(event);
+
+        state.set(GameState::Ended).unwrap();
+    } else {
+        rounds.reset_timer = Some(ROUND_RESET_DELAY);
+    }
+}
+
+pub fn round_client_system(
+    mut events: ResMut<NetworkEvents<RoundEvent>>,
+    mut state: ResMut<State<GameState>>,
+) {
+    for (_sender, event) in events.take() {
+        match event {
+            RoundEvent::RoundStarted(round) => {
+                info!("Round {} started", round);
+
+                if *state.current() != GameState::Duel {
+                    state.overwrite_set(GameState::Duel).unwrap();
+                }
+            }
+            RoundEvent::RoundEnded { round, winner } => {
+                info!("Round {} ended, winner: '{:?}'", round, winner);
+            }
+            RoundEvent::MatchEnded { winner, wins } => {
+                info!("Match ended, winner: '{:?}', wins: {:?}", winner, wins);
+
+                state.overwrite_set(GameState::Ended).unwrap();
+            }
+        }
+    }
+}
+
+/// Shows the hill in [`GameMode::KingOfTheHill`], the image is as wide as the hill.
+pub fn hill_client_system(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    game_settings: Res<GameSettings>,
+    mut materials: ResMut<Assets<ColorMaterial>>,
+) {
+    if game_settings.mode != GameMode::KingOfTheHill {
+        return;
+    }
+
+    commands.spawn_bundle(SpriteBundle {
+        material: materials.add(asset_server.load("misc/hill.png").into()),
+        transform: Transform::from_translation(Vec3::new(0.0, 0.0, HILL_Z)),
+        ..Default::default()
+    });
+}
+
+pub struct RoundPlugin;
+
+impl Plugin for RoundPlugin {
+    fn build(&self, app_builder: &mut AppBuilder) {
+        let is_server = app_builder
+            .world()
+            .get_resource::<NetworkSettings>()
+            .unwrap()
+            .is_server;
+
+        app_builder.register_network_event::<RoundEvent>();
+
+        if is_server {
+            app_builder.insert_resource(Rounds::new());
+            app_builder.add_event::<RoundEvent>();
+            app_builder.add_system_set(
+                SystemSet::on_enter(GameState::Duel)
+                    .with_system(round_start_server_system.system()),
+            );
+            app_builder.add_system_set(
+                SystemSet::on_update(GameState::Duel).with_system(round_server_system.system()),
+            );
+        } else {
+            app_builder.add_system(round_client_system.system());
+            app_builder.add_system_set(
+                SystemSet::on_enter(GameState::Duel).with_system(hill_client_system.system()),
+            );
+        }
+    }
+}
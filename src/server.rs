@@ -1,45 +1,160 @@
 use crate::animation::*;
+use crate::animation_state::*;
 use crate::attack::*;
+use crate::bot::*;
+use crate::client::ClientGreeting;
+use crate::config::*;
+use crate::console::*;
+use crate::dungeon::*;
+use crate::enemy::*;
 use crate::frame::*;
+use crate::game_settings::*;
 use crate::game_state::*;
+use crate::item::*;
+use crate::map::*;
+use crate::match_log::*;
 use crate::networking::*;
 use crate::player::*;
+use crate::round::*;
+use crate::status_effect::*;
+use crate::training::*;
 use crate::transform::*;
+use bevy::app::AppExit;
+use bevy::asset::LoadState;
 use bevy::prelude::*;
 use heron::prelude::*;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc::Receiver;
+use std::sync::Arc;
+
+/// The number of times the server updates per second, frames are counted in ticks.
+///
+/// Frame data and every timer are written for this rate, so the server only runs at it.
+pub const TICK_RATE: f32 = 48.0;
+/// The number of frames a match keeps running after it has ended, so the clients get the stats.
+const MATCH_END_DELAY: u32 = TICK_RATE as u32 * 5;
+
+/// The code players join a match with.
+pub struct MatchCode(pub String);
+
+/// The number of clients the lobby has sent to the match, shared with the lobby.
+pub struct MatchClients(pub Arc<AtomicUsize>);
+
+impl MatchClients {
+    /// Stored instead of a count once the match stops taking clients.
+    pub const CLOSED: usize = usize::MAX;
+
+    /// Gives the slot of a client that left before the game started back to the lobby.
+    pub fn free(&self) {
+        self.0.fetch_sub(1, Ordering::SeqCst);
+    }
+
+    /// Stops the lobby from sending clients, fails if a client is on its way
+    /// to a match that hasn't started.
+    pub fn close(&self, started: bool) -> bool {
+        if started {
+            self.0.store(Self::CLOSED, Ordering::SeqCst);
+            return true;
+        }
+
+        self.0
+            .compare_exchange(0, Self::CLOSED, Ordering::SeqCst, Ordering::SeqCst)
+            .is_ok()
+    }
+}
+
+/// The handles of every asset, loaded in the background when the match starts.
+pub struct AssetHandles(pub Vec<HandleUntyped>);
 
 pub struct Players {
     pub players: Vec<ActorId>,
+    /// The names players picked, bots don't have one.
+    pub names: HashMap<ActorId, String>,
+    /// The frames players play as.
+    pub frames: HashMap<ActorId, String>,
 }
 
 impl Players {
     pub fn new() -> Self {
         Self {
             players: Vec::new(),
+            names: HashMap::new(),
+            frames: HashMap::new(),
         }
     }
+
+    pub fn name(&self, actor_id: &ActorId) -> String {
+        match self.names.get(actor_id) {
+            Some(name) => name.clone(),
+            None => format!("{:?}", actor_id),
+        }
+    }
+
+    /// Removes a player that left, returns false if they weren't one of the players.
+    pub fn remove(&mut self, actor_id: &ActorId) -> bool {
+        self.names.remove(actor_id);
+        self.frames.remove(actor_id);
+
+        let count = self.players.len();
+        self.players.retain(|player| player != actor_id);
+
+        self.players.len() != count
+    }
 }
 
-pub fn run(ip: String) {
-    App::build()
+/// Runs a single match until it has ended,
+/// `bot` is the path of a [`BotDifficulty`] to fill a player slot with.
+///
+/// The clients joining the match are received from `incoming`, counted in `clients`,
+/// and the admin console commands from `console`.
+pub fn run(
+    code: String,
+    config: ServerConfig,
+    game_settings: GameSettings,
+    bot: Option<String>,
+    incoming: Receiver<IncomingConnection>,
+    clients: Arc<AtomicUsize>,
+    console: Receiver<String>,
+) {
+    let training = game_settings.mode == GameMode::Training;
+
+    let mut app_builder = App::build();
+
+    app_builder
         // resources
         .insert_resource(bevy::ecs::schedule::ReportExecutionOrderAmbiguities)
         .insert_resource(bevy::app::ScheduleRunnerSettings::run_loop(
-            std::time::Duration::from_secs_f32(1.0 / 48.0),
+            std::time::Duration::from_secs_f32(1.0 / TICK_RATE),
         ))
+        .insert_resource(MatchCode(code))
+        .insert_resource(MatchClients(clients))
+        .insert_resource(Console::new(console))
         .insert_resource(Players::new())
+        .insert_resource(game_settings)
         .insert_resource(Gravity::from(Vec3::ZERO))
         // plugins
         .add_plugins(MinimalPlugins)
         .add_plugin(bevy::transform::TransformPlugin)
         .add_plugin(bevy::asset::AssetPlugin)
-        .add_plugin(bevy::log::LogPlugin)
-        .add_plugin(NetworkPlugin::server(ip))
+        .add_plugin(NetworkPlugin::server(incoming))
         .add_plugin(PhysicsPlugin::default())
+        .add_plugin(GameSettingsPlugin)
         .add_plugin(PlayerPlugin)
         .add_plugin(FramePlugin)
         .add_plugin(AnimationPlugin)
+        .add_plugin(AnimationStatePlugin)
         .add_plugin(AttackPlugin)
+        .add_plugin(RoundPlugin)
+        .add_plugin(StatusEffectPlugin)
+        .add_plugin(BotPlugin)
+        .add_plugin(TrainingPlugin)
+        .add_plugin(DungeonPlugin)
+        .add_plugin(EnemyPlugin)
+        .add_plugin(ItemPlugin)
+        .add_plugin(MapPlugin)
+        .add_plugin(MatchLogPlugin)
+        .add_plugin(ConsolePlugin)
         // network events
         .register_network_event::<TransformEvent>()
         // network spawnables
@@ -47,64 +162,193 @@ pub fn run(ip: String) {
         .add_state(GameState::Connection)
         // systems
         .add_system(transform_server_system.system())
-        .add_system(connection_system.system())
+        .add_system(connection_system.system().label("connection"))
+        .add_system_set(
+            SystemSet::on_update(GameState::Connection)
+                .with_system(match_start_system.system().after("connection")),
+        )
         .add_system(print_events.system())
+        .add_system_set(
+            SystemSet::on_update(GameState::Ended).with_system(match_end_server_system.system()),
+        )
         // startup systems
-        .add_startup_system(startup_system.system())
-        // run
-        .run();
+        .add_startup_system(startup_system.system());
+
+    if let Some(difficulty) = bot {
+        let actor_id = add_bot(app_builder.world_mut(), difficulty);
+
+        app_builder
+            .world_mut()
+            .get_resource_mut::<Players>()
+            .unwrap()
+            .players
+            .push(actor_id);
+    }
+
+    // the training dummy takes the other player slot
+    if training {
+        let actor_id = add_dummy(app_builder.world_mut());
+
+        app_builder
+            .world_mut()
+            .get_resource_mut::<Players>()
+            .unwrap()
+            .players
+            .push(actor_id);
+    }
+
+    app_builder.insert_resource(config);
+    app_builder.run();
 }
 
 fn startup_system(mut commands: Commands, asset_server: Res<AssetServer>) {
     asset_server.watch_for_changes().unwrap();
     let handles = asset_server.load_folder(".").unwrap();
 
-    commands.insert_resource(handles);
+    commands.insert_resource(AssetHandles(handles));
 }
 
 fn connection_system(
     mut event_reader: EventReader<ConnectionEvent>,
     mut players: ResMut<Players>,
     mut net: ResMut<NetworkResource>,
-    network_spawner: Res<NetworkSpawner>,
+    state: Res<State<GameState>>,
+    mut app_exit_events: EventWriter<AppExit>,
+    event_sender: Res<NetworkEventSender>,
+    game_settings: Res<GameSettings>,
+    config: Res<ServerConfig>,
+    clients: Res<MatchClients>,
 ) {
     for event in event_reader.iter() {
         match event {
-            ConnectionEvent::Connected { id, .. } => {
-                // TODO: use a more dynamic way of checking player count
-                if players.players.len() >= 2 {
+            ConnectionEvent::Connected { id, greeting } => {
+                let player_count = config.max_players();
+
+                if players.players.len() >= player_count {
                     warn!("Player cap exceeded, '{:?}' tired to connect", id);
                     continue;
                 }
 
-                info!("Player connected '{:?}'", id);
-                players.players.push(*id);
-
-                if players.players.len() == 2 {
-                    info!("All players connected, starting game");
+                let greeting = match bincode::deserialize::<ClientGreeting>(&greeting.data) {
+                    Ok(greeting) => greeting,
+                    Err(e) => {
+                        warn!("'{:?}' sent an invalid greeting: '{}'", id, e);
+                        ClientGreeting::default()
+                    }
+                };
 
-                    let mut positions = vec![Vec2::new(100.0, 0.0), Vec2::new(-100.0, 0.0)];
+                let frame = config.frame(&greeting.frame);
 
-                    for id in &players.players {
-                        network_spawner.spawn(PlayerSpawner {
-                            frame: String::from("frames/katana_one/frame.fme"),
-                            player_id: *id,
-                            position: positions.pop().unwrap(),
-                        });
-                    }
+                if frame != greeting.frame {
+                    warn!(
+                        "'{}' picked the frame '{}', which isn't allowed, using '{}'",
+                        greeting.name, greeting.frame, frame
+                    );
                 }
+
+                info!("Player connected '{}' ({:?})", greeting.name, id);
+                players.players.push(*id);
+                players.names.insert(*id, greeting.name);
+                players.frames.insert(*id, frame);
+
+                event_sender.send_to(*id, &*game_settings).unwrap();
             }
             ConnectionEvent::Disconnected { id, error } => {
                 warn!("'{:?}' disconnected with error: '{:?}'", id, error);
 
                 net.remove_connection(&id);
+
+                let started = *state.current() != GameState::Connection;
+
+                // players are spawned when the game starts, until then the slot can be taken
+                if !started && players.remove(id) {
+                    clients.free();
+                }
+
+                // a match nobody is playing anymore is stopped, so the lobby can clean it up
+                if net.connections.is_empty() && clients.close(started) {
+                    info!("Every player has left, stopping the match");
+
+                    app_exit_events.send(AppExit);
+                }
             }
         }
     }
 }
 
+/// Starts the game once every player has connected and every asset has loaded.
+///
+/// The assets are loaded in the background, so the players might have to wait for them.
+fn match_start_system(
+    players: Res<Players>,
+    mut state: ResMut<State<GameState>>,
+    mut app_exit_events: EventWriter<AppExit>,
+    asset_server: Res<AssetServer>,
+    handles: Res<AssetHandles>,
+    maps: Res<Assets<Map>>,
+    network_spawner: Res<NetworkSpawner>,
+    game_settings: Res<GameSettings>,
+    config: Res<ServerConfig>,
+    clients: Res<MatchClients>,
+) {
+    if players.players.len() < config.max_players() {
+        return;
+    }
+
+    match asset_server.get_group_load_state(handles.0.iter().map(|handle| handle.id)) {
+        LoadState::Loaded => (),
+        LoadState::Failed => {
+            error!("Failed to load the assets, stopping the match");
+
+            clients.close(true);
+            app_exit_events.send(AppExit);
+            return;
+        }
+        _ => return,
+    }
+
+    info!("All players connected, starting {:?}", game_settings.mode);
+
+    let map = maps
+        .get(&maps.get_handle(game_settings.map.as_str()))
+        .unwrap();
+
+    for (slot, id) in players.players.iter().enumerate() {
+        // bots don't pick a frame
+        let frame = match players.frames.get(id) {
+            Some(frame) => frame.clone(),
+            None => config.frames[0].clone(),
+        };
+
+        network_spawner.spawn(PlayerSpawner {
+            frame,
+            player_id: *id,
+            team: game_settings.mode.team(slot),
+            position: map.spawn_position(game_settings.mode, slot),
+        });
+    }
+
+    if game_settings.mode == GameMode::Training {
+        state.set(GameState::Training).unwrap();
+    } else if game_settings.dungeon {
+        state.set(GameState::Dungeon).unwrap();
+    } else {
+        state.set(GameState::Duel).unwrap();
+    }
+}
+
+/// Stops the match once the clients have had time to receive the end of match stats.
+fn match_end_server_system(mut timer: Local<u32>, mut app_exit_events: EventWriter<AppExit>) {
+    *timer += 1;
+
+    if *timer == MATCH_END_DELAY {
+        app_exit_events.send(AppExit);
+    }
+}
+
+/// Logs collisions, at the debug level so they don't flood the admin console.
 pub fn print_events(mut events: EventReader<CollisionEvent>) {
     for event in events.iter() {
-        println!("{:?}", event);
+        debug!("{:?}", event);
     }
 }
\ No newline at end of file
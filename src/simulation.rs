@@ -0,0 +1,430 @@
+//! Headless simulation of matches between bots, run with `--simulate`.
+//!
+//! The regular server systems are run without networking, and without heron,
+//! which steps with the real time. Bodies are instead moved with a fixed step
+//! and collisions are detected here, so matches run as fast as possible.
+//!
+//! Every match seeds the bots differently, so the matches don't all play out the same.
+
+use crate::animation::*;
+use crate::animation_state::*;
+use crate::asset_check::*;
+use crate::attack::*;
+use crate::bot::*;
+use crate::frame::*;
+use crate::game_settings::*;
+use crate::game_state::*;
+use crate::networking::*;
+use crate::player::*;
+use crate::round::*;
+use crate::server::TICK_RATE;
+use crate::status_effect::*;
+use bevy::prelude::*;
+use heron::prelude::*;
+use std::collections::{HashMap, HashSet};
+
+/// Matches taking longer than this many ticks are counted as draws.
+const MAX_MATCH_TICKS: u32 = TICK_RATE as u32 * 120;
+
+#[derive(Debug, Hash, PartialEq, Eq, Clone, StageLabel)]
+pub enum SimulationStage {
+    Simulate,
+}
+
+/// The pairs of bodies overlapping in the last tick.
+pub struct SimulationCollisions {
+    pairs: HashSet<(Entity, Entity)>,
+}
+
+/// Statistics of the match being simulated.
+pub struct MatchStats {
+    pub ticks: u32,
+    round_ticks: u32,
+    round_over: bool,
+    health: HashMap<ActorId, f32>,
+    pub damage_taken: HashMap<ActorId, f32>,
+    /// The ticks from the start of each round until the first player died.
+    pub kill_times: Vec<u32>,
+}
+
+impl MatchStats {
+    pub fn new() -> Self {
+        Self {
+            ticks: 0,
+            round_ticks: 0,
+            round_over: false,
+            health: HashMap::new(),
+            damage_taken: HashMap::new(),
+            kill_times: Vec::new(),
+        }
+    }
+}
+
+/// The combined results of the matches between two frames.
+///
+/// The slots of the game mode alternate between the frames, every slot playing
+/// the first frame is on the first side.
+struct MatchupResult {
+    names: [String; 2],
+    matches: u32,
+    wins: [u32; 2],
+    /// The total damage taken by each side.
+    damage_taken: [f32; 2],
+    kill_times: Vec<u32>,
+}
+
+/// Moves bodies by their velocity and sends [`CollisionEvent`]s, in place of heron.
+pub fn simulation_physics_system(
+    mut collisions: ResMut<SimulationCollisions>,
+    mut events: EventWriter<CollisionEvent>,
+    mut query: Query<(&Velocity, &mut Transform)>,
+    body_query: Query<(Entity, &Body, &GlobalTransform)>,
+) {
+    for (velocity, mut transform) in query.iter_mut() {
+        transform.translation += velocity.linear / TICK_RATE;
+    }
+
+    let bodies = body_query.iter().collect::<Vec<_>>();
+    let mut pairs = HashSet::new();
+
+    for i in 0..bodies.len() {
+        for j in i + 1..bodies.len() {
+            let (a, a_body, a_transform) = bodies[i];
+            let (b, b_body, b_transform) = bodies[j];
+
+            if crate::helper::bodies_overlap(a_body, a_transform, b_body, b_transform) {
+                pairs.insert((a.min(b), a.max(b)));
+            }
+        }
+    }
+
+    // sorted, so the simulation doesn't depend on the order of a hash set
+    let mut started = pairs.difference(&collisions.pairs).collect::<Vec<_>>();
+    started.sort();
+
+    let mut stopped = collisions.pairs.difference(&pairs).collect::<Vec<_>>();
+    stopped.sort();
+
+    for (a, b) in started {
+        events.send(CollisionEvent::Started(*a, *b));
+    }
+
+    for (a, b) in stopped {
+        events.send(CollisionEvent::Stopped(*a, *b));
+    }
+
+    collisions.pairs = pairs;
+}
+
+/// Records damage taken and the time until a player dies in each round.
+pub fn simulation_stats_system(mut stats: ResMut<MatchStats>, query: Query<&Player>) {
+    stats.ticks += 1;
+
+    let mut any_dead = false;
+
+    for player in query.iter() {
+        let health = player.health.max(0.0);
+        let last_health = stats.health.insert(player.actor_id, health).unwrap_or(health);
+
+        // health goes up when the players are reset between rounds
+        if health < last_health {
+            *stats.damage_taken.entry(player.actor_id).or_insert(0.0) += last_health - health;
+        }
+
+        any_dead |= player.dead;
+    }
+
+    if any_dead {
+        if !stats.round_over {
+            stats.round_over = true;
+            let round_ticks = stats.round_ticks;
+            stats.kill_times.push(round_ticks);
+        }
+    } else {
+        if stats.round_over {
+            stats.round_over = false;
+            stats.round_ticks = 0;
+        }
+
+        stats.round_ticks += 1;
+    }
+}
+
+fn build_app(mode: GameMode) -> App {
+    let mut app_builder = App::build();
+
+    app_builder
+        // resources
+        .insert_resource(GameSettings {
+            mode,
+            // every match is a single round, so the samples are independent
+            rounds: 1,
+            ..Default::default()
+        })
+        .insert_resource(SimulationCollisions {
+            pairs: HashSet::new(),
+        })
+        .insert_resource(MatchStats::new())
+        // plugins
+        .add_plugin(bevy::core::CorePlugin)
+        .add_plugin(bevy::transform::TransformPlugin)
+        .add_plugin(bevy::asset::AssetPlugin)
+        .add_plugin(NetworkPlugin::offline())
+        .add_plugin(PlayerPlugin)
+        .add_plugin(FramePlugin)
+        .add_plugin(AnimationPlugin)
+        .add_plugin(AnimationStatePlugin)
+        .add_plugin(AttackPlugin)
+        .add_plugin(RoundPlugin)
+        .add_plugin(StatusEffectPlugin)
+        .add_plugin(BotPlugin)
+        // events normally added by heron
+        .add_event::<CollisionEvent>()
+        // state
+        .add_state(GameState::Connection)
+        // systems, the bots think after this stage, which is added right after pre update
+        .add_stage_after(
+            bevy::app::CoreStage::PreUpdate,
+            SimulationStage::Simulate,
+            SystemStage::single_threaded(),
+        )
+        .add_system_to_stage(
+            SimulationStage::Simulate,
+            simulation_physics_system.system().label("physics"),
+        )
+        .add_system_to_stage(
+            SimulationStage::Simulate,
+            simulation_stats_system.system().after("physics"),
+        );
+
+    std::mem::take(&mut app_builder.app)
+}
+
+/// Plays a single match between two frames and adds the outcome to `result`.
+///
+/// The bots are seeded with `seed` and the slot they play.
+fn simulate_match(
+    app: &mut App,
+    mode: GameMode,
+    frames: [&String; 2],
+    difficulty: &str,
+    seed: u32,
+    result: &mut MatchupResult,
+) {
+    let actors = (0..mode.players())
+        .map(|slot| ActorId(slot as u32 + 1))
+        .collect::<Vec<_>>();
+
+    let mut bots = app.world.get_resource_mut::<Bots>().unwrap();
+
+    for (slot, actor) in actors.iter().enumerate() {
+        let bot = Bot::new(seed.wrapping_add(slot as u32), String::from(difficulty));
+        bots.bots.insert(*actor, bot);
+    }
+
+    let network_spawner = app.world.get_resource::<NetworkSpawner>().unwrap();
+
+    for (slot, actor) in actors.iter().enumerate() {
+        network_spawner.spawn(PlayerSpawner {
+            frame: frames[slot % 2].clone(),
+            player_id: *actor,
+            team: mode.team(slot),
+            position: mode.spawn_position(slot),
+        });
+    }
+
+    *app.world.get_resource_mut::<MatchStats>().unwrap() = MatchStats::new();
+
+    app.world
+        .get_resource_mut::<State<GameState>>()
+        .unwrap()
+        .set(GameState::Duel)
+        .unwrap();
+
+    loop {
+        app.update();
+
+        let state = app.world.get_resource::<State<GameState>>().unwrap();
+
+        if *state.current() == GameState::Ended {
+            break;
+        }
+
+        if app.world.get_resource::<MatchStats>().unwrap().ticks >= MAX_MATCH_TICKS {
+            app.world
+                .get_resource_mut::<State<GameState>>()
+                .unwrap()
+                .set(GameState::Ended)
+                .unwrap();
+            app.update();
+            break;
+        }
+    }
+
+    // the side of every team, teams never mix frames
+    let sides = (0..actors.len())
+        .map(|slot| (mode.team(slot), slot % 2))
+        .collect::<HashMap<_, _>>();
+
+    let rounds = app.world.get_resource::<Rounds>().unwrap();
+    let most_wins = sides
+        .keys()
+        .map(|team| rounds.wins(team))
+        .max()
+        .unwrap_or(0);
+    let winners = sides
+        .iter()
+        .filter(|(team, _)| rounds.wins(team) == most_wins)
+        .collect::<Vec<_>>();
+
+    if most_wins > 0 && winners.len() == 1 {
+        result.wins[*winners[0].1] += 1;
+    }
+
+    let stats = app.world.get_resource::<MatchStats>().unwrap();
+
+    for (slot, actor) in actors.iter().enumerate() {
+        result.damage_taken[slot % 2] += stats.damage_taken.get(actor).cloned().unwrap_or(0.0);
+    }
+
+    result.kill_times.extend(stats.kill_times.iter());
+    result.matches += 1;
+
+    clear_match(&mut app.world);
+}
+
+/// Despawns everything spawned during a match and resets the rounds.
+fn clear_match(world: &mut World) {
+    let mut query = world.query::<(Entity, &NetworkEntity)>();
+    let entities = query
+        .iter(world)
+        .map(|(entity, network_entity)| (entity, *network_entity))
+        .collect::<Vec<_>>();
+
+    for (entity, network_entity) in entities {
+        world
+            .get_resource_mut::<NetworkEntityRegistry>()
+            .unwrap()
+            .remove(&network_entity);
+        world.despawn(entity);
+    }
+
+    world
+        .get_resource_mut::<SimulationCollisions>()
+        .unwrap()
+        .pairs
+        .clear();
+
+    world.get_resource_mut::<Bots>().unwrap().bots.clear();
+    *world.get_resource_mut::<Rounds>().unwrap() = Rounds::new();
+}
+
+fn percentage(count: u32, total: u32) -> String {
+    format!("{:.1}%", count as f32 / total.max(1) as f32 * 100.0)
+}
+
+fn results_table(results: &[MatchupResult]) -> String {
+    let mut output = String::from(
+        "| Matchup | Matches | Wins | Draws | Average damage taken | Average time to kill |\n\
+         |---|---|---|---|---|---|\n",
+    );
+
+    for result in results {
+        let matches = result.matches.max(1) as f32;
+        let draws = result.matches - result.wins[0] - result.wins[1];
+
+        let time_to_kill = if result.kill_times.is_empty() {
+            String::from("-")
+        } else {
+            let ticks = result.kill_times.iter().sum::<u32>() as f32 / result.kill_times.len() as f32;
+            format!("{:.2}s", ticks / TICK_RATE)
+        };
+
+        output += &format!(
+            "| {} vs {} | {} | {} / {} | {} | {:.1} / {:.1} | {} |\n",
+            result.names[0],
+            result.names[1],
+            result.matches,
+            percentage(result.wins[0], result.matches),
+            percentage(result.wins[1], result.matches),
+            percentage(draws, result.matches),
+            result.damage_taken[0] / matches,
+            result.damage_taken[1] / matches,
+            time_to_kill,
+        );
+    }
+
+    output
+}
+
+/// Simulates `matches` matches in the game mode for every pair of frames in `root`,
+/// played by bots of the difficulty at the path, and prints the results.
+///
+/// Returns false if the assets are invalid.
+pub fn simulate(root: &str, matches: u32, mode: GameMode, difficulty: &str) -> bool {
+    let mut checker = AssetChecker::new(root);
+
+    if let Err(e) = checker.check() {
+        eprintln!("failed to read '{}': {}", root, e);
+        return false;
+    }
+
+    if !checker.errors().is_empty() {
+        for error in checker.errors() {
+            eprintln!("{}", error);
+        }
+
+        return false;
+    }
+
+    if !checker.bot_difficulties().contains_key(difficulty) {
+        eprintln!("there's no bot difficulty at '{}'", difficulty);
+        return false;
+    }
+
+    // enemy frames aren't played by players
+    let mut frame_paths = checker
+        .frames()
+        .keys()
+        .filter(|path| !checker.enemies().values().any(|enemy| enemy.frame == **path))
+        .cloned()
+        .collect::<Vec<_>>();
+    frame_paths.sort();
+
+    let names = checker
+        .frames()
+        .iter()
+        .map(|(path, frame)| (path.clone(), frame.name.clone()))
+        .collect::<HashMap<_, _>>();
+
+    let mut app = build_app(mode);
+    checker.insert_assets(&mut app.world);
+
+    let mut results = Vec::new();
+
+    for (i, a) in frame_paths.iter().enumerate() {
+        for b in &frame_paths[i..] {
+            eprintln!("Simulating {} matches of '{}' vs '{}'", matches, a, b);
+
+            let mut result = MatchupResult {
+                names: [names[a].clone(), names[b].clone()],
+                matches: 0,
+                wins: [0, 0],
+                damage_taken: [0.0, 0.0],
+                kill_times: Vec::new(),
+            };
+
+            for i in 0..matches {
+                // far enough apart that the slots of different matches never share a seed
+                let seed = i.wrapping_mul(mode.players() as u32);
+                simulate_match(&mut app, mode, [a, b], difficulty, seed, &mut result);
+            }
+
+            results.push(result);
+        }
+    }
+
+    print!("{}", results_table(&results));
+
+    true
+}
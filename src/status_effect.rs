@@ -0,0 +1,290 @@
+use crate::networking::*;
+use crate::player::*;
+use bevy::prelude::*;
+use std::collections::HashMap;
+
+/// The size of the status effect icons shown above the health bar.
+const ICON_SIZE: f32 = 12.0;
+
+#[derive(Serialize, Deserialize, TypeUuid)]
+#[uuid = "3e9c6f0a-7d24-4b8e-b1f5-92a0c4d7e318"]
+pub enum StatusEffectEvent {
+    /// Sets the active effects of a player, as effect paths and stacks.
+    SetEffects(NetworkEntity, Vec<(String, u32)>),
+}
+
+#[derive(Serialize, Deserialize)]
+pub enum Stacking {
+    /// Reapplying the effect resets its duration.
+    Refresh,
+    /// Reapplying the effect adds a stack, up to `max_stacks`, and resets its duration.
+    Stack,
+    /// Reapplying the effect does nothing while it's active.
+    Ignore,
+}
+
+#[derive(Serialize, Deserialize, TypeUuid)]
+#[serde(deny_unknown_fields)]
+#[uuid = "c81d5b27-4a6e-4f93-8e0d-1b7a9f3c6e52"]
+pub struct StatusEffect {
+    pub name: String,
+    pub icon: String,
+    /// The duration in frames.
+    pub duration: u32,
+    /// Damage dealt per stack every `tick_interval` frames.
+    #[serde(default)]
+    pub tick_damage: f32,
+    #[serde(default)]
+    pub tick_interval: u32,
+    /// Multiplies the walking speed, once per stack.
+    #[serde(default = "crate::helper::one")]
+    pub speed_multiplier: f32,
+    /// Multiplies incoming damage, once per stack.
+    #[serde(default = "crate::helper::one")]
+    pub damage_taken_multiplier: f32,
+    pub stacking: Stacking,
+    #[serde(default = "one_stack")]
+    pub max_stacks: u32,
+}
+
+fn one_stack() -> u32 {
+    1
+}
+
+pub struct ActiveStatusEffect {
+    pub effect: String,
+    pub remaining: u32,
+    pub stacks: u32,
+    /// Frames since the last damage tick.
+    pub tick: u32,
+}
+
+/// The status effects currently active on a player.
+pub struct StatusEffects {
+    pub effects: Vec<ActiveStatusEffect>,
+    pub update: bool,
+}
+
+impl StatusEffects {
+    pub fn new() -> Self {
+        Self {
+            effects: Vec::new(),
+            update: false,
+        }
+    }
+
+    /// Applies the effect loaded from `path`, following its stacking rules.
+    pub fn apply(&mut self, path: &str, effect: &StatusEffect) {
+        if let Some(active) = self.effects.iter_mut().find(|active| active.effect == path) {
+            match effect.stacking {
+                Stacking::Refresh => {
+                    active.remaining = effect.duration;
+                }
+                Stacking::Stack => {
+                    active.stacks = (active.stacks + 1).min(effect.max_stacks);
+                    active.remaining = effect.duration;
+                }
+                Stacking::Ignore => return,
+            }
+        } else {
+            self.effects.push(ActiveStatusEffect {
+                effect: String::from(path),
+                remaining: effect.duration,
+                stacks: 1,
+                tick: 0,
+            });
+        }
+
+        self.update = true;
+    }
+
+    pub fn clear(&mut self) {
+        self.effects.clear();
+        self.update = true;
+    }
+}
+
+/// The icons shown on the client for the active status effects of a player.
+pub struct StatusEffectIcons {
+    pub icons: Vec<Entity>,
+}
+
+/// One material per icon texture, shared by every icon showing it.
+pub struct StatusEffectMaterials {
+    pub materials: HashMap<String, Handle<ColorMaterial>>,
+}
+
+pub fn status_effect_server_system(
+    status_effects: Res<Assets<StatusEffect>>,
+    event_sender: Res<NetworkEventSender>,
+    mut query: Query<(&NetworkEntity, &mut Player, &mut StatusEffects)>,
+) {
+    for (network_entity, mut player, mut active_effects) in query.iter_mut() {
+        let mut speed_multiplier = 1.0;
+        let mut damage_taken_multiplier = 1.0;
+        let mut tick_damage = 0.0;
+
+        for active in active_effects.effects.iter_mut() {
+            let effect = status_effects.get(active.effect.as_str()).unwrap();
+
+            active.remaining = active.remaining.saturating_sub(1);
+            active.tick += 1;
+
+            if effect.tick_interval > 0 && active.tick >= effect.tick_interval {
+                active.tick = 0;
+                tick_damage += effect.tick_damage * active.stacks as f32;
+            }
+
+            speed_multiplier *= effect.speed_multiplier.powi(active.stacks as i32);
+            damage_taken_multiplier *= effect.damage_taken_multiplier.powi(active.stacks as i32);
+        }
+
+        let count = active_effects.effects.len();
+        active_effects.effects.retain(|active| active.remaining > 0);
+
+        if active_effects.effects.len() != count {
+            active_effects.update = true;
+        }
+
+        player.speed_multiplier = speed_multiplier;
+        player.damage_taken_multiplier = damage_taken_multiplier;
+
+        if tick_damage > 0.0 && !player.dead {
+            player.damage(tick_damage);
+        }
+
+        if active_effects.update {
+            active_effects.update = false;
+
+            let effects = active_effects
+                .effects
+                .iter()
+                .map(|active| (active.effect.clone(), active.stacks))
+                .collect();
+
+            event_sender
+                .send(&StatusEffectEvent::SetEffects(*network_entity, effects))
+                .unwrap();
+        }
+    }
+}
+
+pub fn status_effect_client_system(
+    mut commands: Commands,
+    mut events: ResMut<NetworkEvents<StatusEffectEvent>>,
+    network_entity_registry: Res<NetworkEntityRegistry>,
+    asset_server: Res<AssetServer>,
+    status_effects: Res<Assets<StatusEffect>>,
+    textures: Res<Assets<Texture>>,
+    mut materials: ResMut<Assets<ColorMaterial>>,
+    mut icon_materials: ResMut<StatusEffectMaterials>,
+    mut query: Query<(&mut StatusEffectIcons, &mut Player)>,
+) {
+    for (_sender, event) in events.take() {
+        match event {
+            StatusEffectEvent::SetEffects(network_entity, effects) => {
+                let entity = network_entity_registry.get(&network_entity).unwrap();
+
+                let (mut status_effect_icons, mut player) = query.get_mut(entity).unwrap();
+
+                // the animation state machine needs the speed of the player
+                player.speed_multiplier = effects
+                    .iter()
+                    .map(|(effect, stacks)| {
+                        let effect = status_effects.get(effect.as_str()).unwrap();
+                        effect.speed_multiplier.powi(*stacks as i32)
+                    })
+                    .product();
+
+                for icon in status_effect_icons.icons.drain(..) {
+                    commands.entity(icon).despawn_recursive();
+                }
+
+                // center the icons above the health bar
+                let offset = (effects.len() as f32 - 1.0) / 2.0;
+
+                for (i, (effect, stacks)) in effects.iter().enumerate() {
+                    let effect = status_effects.get(effect.as_str()).unwrap();
+                    let material = icon_materials
+                        .materials
+                        .entry(effect.icon.clone())
+                        .or_insert_with(|| {
+                            let texture = textures.get_handle(effect.icon.as_str());
+                            materials.add(texture.into())
+                        })
+                        .clone();
+
+                    let x = (i as f32 - offset) * (ICON_SIZE + 2.0);
+
+                    let icon = commands
+                        .spawn_bundle(SpriteBundle {
+                            material,
+                            sprite: Sprite::new(Vec2::splat(ICON_SIZE)),
+                            transform: Transform::from_translation(Vec3::new(x, 110.0, 0.0)),
+                            ..Default::default()
+                        })
+                        .with_children(|parent| {
+                            if *stacks <= 1 {
+                                return;
+                            }
+
+                            // the stack count in the bottom right corner of the icon
+                            parent.spawn_bundle(Text2dBundle {
+                                text: Text::with_section(
+                                    stacks.to_string(),
+                                    TextStyle {
+                                        font: asset_server.load("fonts/DejaVuSansMono.ttf"),
+                                        font_size: 10.0,
+                                        color: Color::WHITE,
+                                    },
+                                    TextAlignment {
+                                        vertical: VerticalAlign::Center,
+                                        horizontal: HorizontalAlign::Center,
+                                    },
+                                ),
+                                transform: Transform::from_translation(Vec3::new(
+                                    ICON_SIZE / 2.0,
+                                    -ICON_SIZE / 2.0,
+                                    1.0,
+                                )),
+                                ..Default::default()
+                            });
+                        })
+                        .id();
+
+                    commands.entity(entity).push_children(&[icon]);
+                    status_effect_icons.icons.push(icon);
+                }
+            }
+        }
+    }
+}
+
+pub struct StatusEffectLoader;
+
+crate::ron_loader!(StatusEffectLoader, "eff" => StatusEffect);
+
+pub struct StatusEffectPlugin;
+
+impl Plugin for StatusEffectPlugin {
+    fn build(&self, app_builder: &mut AppBuilder) {
+        let is_server = app_builder
+            .world()
+            .get_resource::<NetworkSettings>()
+            .unwrap()
+            .is_server;
+
+        app_builder.add_asset::<StatusEffect>();
+        app_builder.add_asset_loader(StatusEffectLoader);
+        app_builder.register_network_event::<StatusEffectEvent>();
+
+        if is_server {
+            app_builder.add_system(status_effect_server_system.system());
+        } else {
+            app_builder.insert_resource(StatusEffectMaterials {
+                materials: HashMap::new(),
+            });
+            app_builder.add_system(status_effect_client_system.system());
+        }
+    }
+}
@@ -0,0 +1,628 @@
+//! Training mode, a single player against a dummy with a frame data overlay.
+
+use crate::animation::*;
+use crate::attack::*;
+use crate::bot::*;
+use crate::frame::*;
+use crate::game_state::*;
+use crate::input::*;
+use crate::networking::*;
+use crate::player::*;
+use crate::round::*;
+use crate::server::TICK_RATE;
+use bevy::prelude::*;
+
+/// The number of frames between a player dying and everyone being reset.
+const TRAINING_RESET_DELAY: u32 = TICK_RATE as u32;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum DummyBehaviour {
+    Stand,
+    BlockAll,
+    /// Decides whether to block every time the player starts an attack.
+    RandomBlock,
+    /// Replays the input recorded from the player.
+    Replay,
+}
+
+impl DummyBehaviour {
+    /// The behaviour after this one, for cycling through them.
+    pub fn next(self) -> Self {
+        match self {
+            DummyBehaviour::Stand => DummyBehaviour::BlockAll,
+            DummyBehaviour::BlockAll => DummyBehaviour::RandomBlock,
+            DummyBehaviour::RandomBlock => DummyBehaviour::Replay,
+            DummyBehaviour::Replay => DummyBehaviour::Stand,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, TypeUuid)]
+#[uuid = "f2a86c3d-5b17-4e90-8d4a-3c6e1b7f9a05"]
+pub enum TrainingInputEvent {
+    SetDummy(DummyBehaviour),
+    /// Starts recording the player's input, or stops and keeps the recording.
+    ToggleRecording,
+    /// Resets the positions and health of all players.
+    Reset,
+}
+
+#[derive(Serialize, Deserialize, TypeUuid)]
+#[uuid = "68d1e4b9-0a7c-4f23-95b6-e2c8d3a1f7b4"]
+pub enum TrainingEvent {
+    SetDummy {
+        behaviour: DummyBehaviour,
+        recording: bool,
+    },
+    /// A hit connected, `combo` counts the hits the target couldn't act between.
+    Hit {
+        damage: f32,
+        result: HitResult,
+        combo: u32,
+    },
+    /// The frames the attacker could act before the target after the last hit.
+    Advantage(i32),
+}
+
+/// The frames the attacker and the target of a hit became free to act on.
+struct PendingAdvantage {
+    attacker: NetworkEntity,
+    target: NetworkEntity,
+    attacker_free: Option<u32>,
+    target_free: Option<u32>,
+}
+
+/// The training state on the server.
+pub struct Training {
+    pub dummy: Option<ActorId>,
+    pub behaviour: DummyBehaviour,
+    tick: u32,
+    /// The input being recorded from the player, with the frames since recording started.
+    /// Directions are recorded relative to the direction from the player to the dummy.
+    recording: Option<Vec<(u32, PlayerInputEvent)>>,
+    recording_start: u32,
+    recorded: Vec<(u32, PlayerInputEvent)>,
+    replay_tick: u32,
+    reset_requested: bool,
+    reset_timer: Option<u32>,
+    combo: u32,
+    combo_target: Option<NetworkEntity>,
+    /// True if the target of the combo has been able to act since the last hit.
+    combo_target_free: bool,
+    advantage: Option<PendingAdvantage>,
+    /// If the player was attacking last frame, for [`DummyBehaviour::RandomBlock`].
+    player_attacking: bool,
+    random_block: bool,
+    rng: u32,
+}
+
+impl Training {
+    pub fn new() -> Self {
+        Self {
+            dummy: None,
+            behaviour: DummyBehaviour::Stand,
+            tick: 0,
+            recording: None,
+            recording_start: 0,
+            recorded: Vec::new(),
+            replay_tick: 0,
+            reset_requested: false,
+            reset_timer: None,
+            combo: 0,
+            combo_target: None,
+            combo_target_free: true,
+            advantage: None,
+            player_attacking: false,
+            random_block: false,
+            rng: 0x9e3779b9,
+        }
+    }
+
+    /// Returns a pseudo random number between 0 and 1.
+    fn random(&mut self) -> f32 {
+        self.rng ^= self.rng << 13;
+        self.rng ^= self.rng >> 17;
+        self.rng ^= self.rng << 5;
+
+        self.rng as f32 / u32::MAX as f32
+    }
+}
+
+/// Reserves an [`ActorId`] for the training dummy, the player still has to be spawned.
+pub fn add_dummy(world: &mut World) -> ActorId {
+    let actor_id = world
+        .get_resource_mut::<NetworkResource>()
+        .unwrap()
+        .reserve_id();
+
+    world.get_resource_mut::<Training>().unwrap().dummy = Some(actor_id);
+
+    actor_id
+}
+
+/// Returns a copy of the input event for another player, with its directions mapped.
+fn with_entity(
+    event: &PlayerInputEvent,
+    network_entity: NetworkEntity,
+    map: impl Fn(Vec2) -> Vec2,
+) -> PlayerInputEvent {
+    match event {
+        PlayerInputEvent::SetMovement(_, movement) => {
+            PlayerInputEvent::SetMovement(network_entity, map(*movement))
+        }
+        PlayerInputEvent::Attack(_, attack_type) => {
+            PlayerInputEvent::Attack(network_entity, *attack_type)
+        }
+        PlayerInputEvent::SetAimDirection(_, direction) => {
+            PlayerInputEvent::SetAimDirection(network_entity, map(*direction))
+        }
+        PlayerInputEvent::SetBlock(_, block) => PlayerInputEvent::SetBlock(network_entity, *block),
+    }
+}
+
+/// The direction from one position to another, or right if they are the same.
+fn axis(from: Vec2, to: Vec2) -> Vec2 {
+    let offset = to - from;
+
+    if offset.length_squared() > 0.0 {
+        offset.normalize()
+    } else {
+        Vec2::new(1.0, 0.0)
+    }
+}
+
+/// Rotates a direction so `axis` points right.
+fn to_axis(direction: Vec2, axis: Vec2) -> Vec2 {
+    Vec2::new(direction.dot(axis), direction.dot(axis.perp()))
+}
+
+/// Rotates a direction so right points along `axis`, undoing [`to_axis`].
+fn from_axis(direction: Vec2, axis: Vec2) -> Vec2 {
+    axis * direction.x + axis.perp() * direction.y
+}
+
+pub fn training_start_server_system(training: Res<Training>, event_sender: Res<NetworkEventSender>) {
+    event_sender
+        .send(&TrainingEvent::SetDummy {
+            behaviour: training.behaviour,
+            recording: training.recording.is_some(),
+        })
+        .unwrap();
+}
+
+/// Handles the training settings, records the player's input and controls the dummy.
+pub fn training_input_server_system(
+    state: Res<State<GameState>>,
+    event_sender: Res<NetworkEventSender>,
+    mut training: ResMut<Training>,
+    mut training_events: ResMut<NetworkEvents<TrainingInputEvent>>,
+    mut events: ResMut<NetworkEvents<PlayerInputEvent>>,
+    query: Query<(&NetworkEntity, &Player, &Transform)>,
+) {
+    if *state.current() != GameState::Training {
+        return;
+    }
+
+    let dummy_id = match training.dummy {
+        Some(dummy_id) => dummy_id,
+        None => return,
+    };
+
+    training.tick += 1;
+    let tick = training.tick;
+
+    let training_events = training_events.take();
+
+    for (_sender, event) in &training_events {
+        match event {
+            TrainingInputEvent::SetDummy(behaviour) => {
+                training.behaviour = *behaviour;
+                training.replay_tick = 0;
+            }
+            TrainingInputEvent::ToggleRecording => {
+                if let Some(recording) = training.recording.take() {
+                    training.recorded = recording;
+                    training.replay_tick = 0;
+                } else {
+                    training.recording = Some(Vec::new());
+                    training.recording_start = tick;
+                }
+            }
+            TrainingInputEvent::Reset => training.reset_requested = true,
+        }
+    }
+
+    if !training_events.is_empty() {
+        event_sender
+            .send(&TrainingEvent::SetDummy {
+                behaviour: training.behaviour,
+                recording: training.recording.is_some(),
+            })
+            .unwrap();
+    }
+
+    let mut dummy = None;
+    let mut player_position = None;
+    let mut player_attacking = false;
+
+    for (network_entity, player, transform) in query.iter() {
+        let position = transform.translation.truncate();
+
+        if player.actor_id == dummy_id {
+            dummy = Some((*network_entity, player, position));
+        } else {
+            player_position = Some(position);
+            player_attacking |= player.attacking;
+        }
+    }
+
+    let (dummy_entity, dummy, dummy_position) = match dummy {
+        Some(dummy) => dummy,
+        None => return,
+    };
+
+    // record the player's input, so the dummy can replay it facing the player
+    let recording_start = training.recording_start;
+    let player_position = player_position.unwrap_or(dummy_position);
+    let recording_axis = axis(player_position, dummy_position);
+
+    if let Some(recording) = &mut training.recording {
+        for (sender, event) in events.iter() {
+            if *sender != dummy_id {
+                let event = with_entity(event, dummy_entity, |direction| {
+                    to_axis(direction, recording_axis)
+                });
+                recording.push((tick - recording_start, event));
+            }
+        }
+    }
+
+    let started_attacking = player_attacking && !training.player_attacking;
+    training.player_attacking = player_attacking;
+
+    let block = match training.behaviour {
+        DummyBehaviour::Stand => false,
+        DummyBehaviour::BlockAll => true,
+        DummyBehaviour::RandomBlock => {
+            if started_attacking {
+                training.random_block = training.random() < 0.5;
+            }
+
+            training.random_block
+        }
+        DummyBehaviour::Replay => {
+            let length = training.recorded.last().map_or(0, |(tick, _)| *tick);
+            let replay_axis = axis(dummy_position, player_position);
+
+            for (_, event) in training
+                .recorded
+                .iter()
+                .filter(|(tick, _)| *tick == training.replay_tick)
+            {
+                let event = with_entity(event, dummy_entity, |direction| {
+                    from_axis(direction, replay_axis)
+                });
+                events.push(dummy_id, event);
+            }
+
+            // loop the recording
+            if training.replay_tick >= length {
+                training.replay_tick = 0;
+            } else {
+                training.replay_tick += 1;
+            }
+
+            return;
+        }
+    };
+
+    if dummy.movement_vector != Vec2::ZERO {
+        events.push(
+            dummy_id,
+            PlayerInputEvent::SetMovement(dummy_entity, Vec2::ZERO),
+        );
+    }
+
+    if block != dummy.block_held {
+        events.push(dummy_id, PlayerInputEvent::SetBlock(dummy_entity, block));
+    }
+}
+
+/// Tracks hits, combos and frame advantage, and resets the players when asked or when one dies.
+pub fn training_server_system(
+    mut commands: Commands,
+    frames: Res<Assets<Frame>>,
+    event_sender: Res<NetworkEventSender>,
+    mut training: ResMut<Training>,
+    mut hit_events: EventReader<HitEvent>,
+    mut query: Query<ResetQuery>,
+) {
+    if training.dummy.is_none() {
+        return;
+    }
+
+    let tick = training.tick;
+
+    for hit in hit_events.iter() {
+        if hit.result == HitResult::Hit || hit.result == HitResult::GuardBreak {
+            if training.combo_target != Some(hit.target) || training.combo_target_free {
+                training.combo = 0;
+            }
+
+            training.combo += 1;
+            training.combo_target = Some(hit.target);
+            training.combo_target_free = false;
+        }
+
+        event_sender
+            .send(&TrainingEvent::Hit {
+                damage: hit.damage,
+                result: hit.result,
+                combo: training.combo,
+            })
+            .unwrap();
+
+        training.advantage = Some(PendingAdvantage {
+            attacker: hit.source,
+            target: hit.target,
+            attacker_free: None,
+            target_free: None,
+        });
+    }
+
+    let mut any_dead = false;
+
+    for (_, network_entity, player, _, _, _, _, _) in query.iter_mut() {
+        let free = !player.attacking && player.stun.is_none();
+        any_dead |= player.dead;
+
+        if free && training.combo_target == Some(*network_entity) {
+            training.combo_target_free = true;
+        }
+
+        if let Some(advantage) = &mut training.advantage {
+            if free && advantage.attacker == *network_entity && advantage.attacker_free.is_none() {
+                advantage.attacker_free = Some(tick);
+            }
+
+            if free && advantage.target == *network_entity && advantage.target_free.is_none() {
+                advantage.target_free = Some(tick);
+            }
+        }
+    }
+
+    let advantage = match &training.advantage {
+        Some(PendingAdvantage {
+            attacker_free: Some(attacker_free),
+            target_free: Some(target_free),
+            ..
+        }) => Some(*target_free as i32 - *attacker_free as i32),
+        _ => None,
+    };
+
+    if let Some(advantage) = advantage {
+        training.advantage = None;
+
+        event_sender
+            .send(&TrainingEvent::Advantage(advantage))
+            .unwrap();
+    }
+
+    if any_dead && training.reset_timer.is_none() {
+        training.reset_timer = Some(TRAINING_RESET_DELAY);
+    }
+
+    match training.reset_timer {
+        Some(0) => {
+            training.reset_timer = None;
+            training.reset_requested = true;
+        }
+        Some(timer) => training.reset_timer = Some(timer - 1),
+        None => {}
+    }
+
+    if training.reset_requested {
+        training.reset_requested = false;
+        training.reset_timer = None;
+        training.combo = 0;
+        training.combo_target = None;
+        training.advantage = None;
+
+        reset_players(&mut commands, &frames, &event_sender, &mut query);
+    }
+}
+
+/// The training overlay on the client.
+pub struct TrainingOverlay {
+    /// The text entity, spawned when the server starts training.
+    text: Option<Entity>,
+    behaviour: DummyBehaviour,
+    recording: bool,
+    last_hit: Option<(f32, HitResult)>,
+    combo: u32,
+    advantage: Option<i32>,
+}
+
+impl TrainingOverlay {
+    pub fn new() -> Self {
+        Self {
+            text: None,
+            behaviour: DummyBehaviour::Stand,
+            recording: false,
+            last_hit: None,
+            combo: 0,
+            advantage: None,
+        }
+    }
+}
+
+pub fn training_client_system(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    player_resource: Res<PlayerResource>,
+    mut overlay: ResMut<TrainingOverlay>,
+    mut events: ResMut<NetworkEvents<TrainingEvent>>,
+    mut state: ResMut<State<GameState>>,
+    animator_query: Query<&Animator>,
+    mut text_query: Query<&mut Text>,
+) {
+    for (_sender, event) in events.take() {
+        match event {
+            TrainingEvent::SetDummy {
+                behaviour,
+                recording,
+            } => {
+                overlay.behaviour = behaviour;
+                overlay.recording = recording;
+            }
+            TrainingEvent::Hit {
+                damage,
+                result,
+                combo,
+            } => {
+                overlay.last_hit = Some((damage, result));
+                overlay.combo = combo;
+            }
+            TrainingEvent::Advantage(advantage) => {
+                overlay.advantage = Some(advantage);
+            }
+        }
+
+        if *state.current() != GameState::Training {
+            state.overwrite_set(GameState::Training).unwrap();
+        }
+
+        if overlay.text.is_none() {
+            commands.spawn_bundle(UiCameraBundle::default());
+
+            let text = commands
+                .spawn_bundle(TextBundle {
+                    style: Style {
+                        position_type: PositionType::Absolute,
+                        position: Rect {
+                            top: Val::Px(8.0),
+                            left: Val::Px(8.0),
+                            ..Default::default()
+                        },
+                        ..Default::default()
+                    },
+                    text: Text::with_section(
+                        "",
+                        TextStyle {
+                            font: asset_server.load("fonts/DejaVuSansMono.ttf"),
+                            font_size: 18.0,
+                            color: Color::WHITE,
+                        },
+                        Default::default(),
+                    ),
+                    ..Default::default()
+                })
+                .id();
+
+            overlay.text = Some(text);
+        }
+    }
+
+    let mut text = match overlay.text.and_then(|text| text_query.get_mut(text).ok()) {
+        Some(text) => text,
+        None => return,
+    };
+
+    let animator_frame = player_resource
+        .player_entity
+        .and_then(|entity| animator_query.get(entity).ok())
+        .map_or(String::from("-"), |animator| animator.frame().to_string());
+
+    let last_hit = overlay
+        .last_hit
+        .map_or(String::from("-"), |(damage, result)| {
+            format!("{:.1} ({:?})", damage, result)
+        });
+
+    let advantage = overlay
+        .advantage
+        .map_or(String::from("-"), |advantage| format!("{:+}", advantage));
+
+    text.sections[0].value = format!(
+        "Dummy: {:?}{}\nLast hit: {}\nCombo: {}\nAdvantage: {}\nAnimation frame: {}",
+        overlay.behaviour,
+        if overlay.recording { " (recording)" } else { "" },
+        last_hit,
+        overlay.combo,
+        advantage,
+        animator_frame,
+    );
+}
+
+pub fn training_input_system(
+    overlay: Res<TrainingOverlay>,
+    input_handle: Res<Handle<InputSettings>>,
+    input_settings: Res<Assets<InputSettings>>,
+    keyboard_input: Res<Input<KeyCode>>,
+    mouse_input: Res<Input<MouseButton>>,
+    event_sender: Res<NetworkEventSender>,
+) {
+    // only send training input while training
+    if overlay.text.is_none() {
+        return;
+    }
+
+    let input_ctx = InputCtx {
+        keyboard: &*keyboard_input,
+        mouse: &*mouse_input,
+    };
+
+    let input = input_settings.get(&*input_handle).unwrap();
+
+    if input.training_dummy.just_pressed(&input_ctx) {
+        event_sender
+            .send(&TrainingInputEvent::SetDummy(overlay.behaviour.next()))
+            .unwrap();
+    }
+
+    if input.training_record.just_pressed(&input_ctx) {
+        event_sender
+            .send(&TrainingInputEvent::ToggleRecording)
+            .unwrap();
+    }
+
+    if input.training_reset.just_pressed(&input_ctx) {
+        event_sender.send(&TrainingInputEvent::Reset).unwrap();
+    }
+}
+
+/// Adds training mode, must be added after the [`BotPlugin`], which adds the [`BotStage`].
+pub struct TrainingPlugin;
+
+impl Plugin for TrainingPlugin {
+    fn build(&self, app_builder: &mut AppBuilder) {
+        let is_server = app_builder
+            .world()
+            .get_resource::<NetworkSettings>()
+            .unwrap()
+            .is_server;
+
+        app_builder.register_network_event::<TrainingInputEvent>();
+        app_builder.register_network_event::<TrainingEvent>();
+
+        if is_server {
+            app_builder.insert_resource(Training::new());
+            app_builder.add_system_to_stage(
+                BotStage::Think,
+                training_input_server_system.system(),
+            );
+            app_builder.add_system_set(
+                SystemSet::on_enter(GameState::Training)
+                    .with_system(training_start_server_system.system()),
+            );
+            app_builder.add_system_set(
+                SystemSet::on_update(GameState::Training)
+                    .with_system(training_server_system.system()),
+            );
+        } else {
+            app_builder.insert_resource(TrainingOverlay::new());
+            app_builder.add_system(training_client_system.system());
+            app_builder.add_system(training_input_system.system());
+        }
+    }
+}